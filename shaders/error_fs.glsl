@@ -0,0 +1,8 @@
+#version 150
+
+out vec4 out_color;
+
+void main() {
+   float checker = mod(floor(gl_FragCoord.x / 8.0) + floor(gl_FragCoord.y / 8.0), 2.0);
+   out_color = mix(vec4(1.0, 0.0, 1.0, 1.0), vec4(0.0, 0.0, 0.0, 1.0), checker);
+}
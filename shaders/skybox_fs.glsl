@@ -0,0 +1,11 @@
+#version 150
+
+in vec3 Direction;
+
+out vec4 out_color;
+
+uniform samplerCube skybox;
+
+void main() {
+   out_color = texture(skybox, normalize(Direction));
+}
@@ -0,0 +1,17 @@
+#version 150
+
+in vec3 position;
+
+out vec3 Direction;
+
+uniform mat4 viewProjection;
+
+void main() {
+  // position is a fullscreen quad in normalized device coordinates. Unprojecting it at the near
+  // and far plane gives the view direction, without the camera translation.
+  mat4 inverseViewProjection = inverse(viewProjection);
+  vec4 near = inverseViewProjection * vec4(position.xy, -1.0, 1.0);
+  vec4 far = inverseViewProjection * vec4(position.xy, 1.0, 1.0);
+  Direction = far.xyz / far.w - near.xyz / near.w;
+  gl_Position = vec4(position.xy, 1.0, 1.0);
+}
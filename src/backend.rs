@@ -0,0 +1,699 @@
+extern crate gl;
+
+use gl::types::*;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::ffi::CString;
+use std::fmt::Debug;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::str;
+
+pub trait RenderBackend : Debug {
+    fn create_buffer(&self, target: GLenum, data: &[u8], usage: GLenum) -> GLuint;
+    fn bind_buffer(&self, target: GLenum, buffer: GLuint);
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum);
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]);
+    fn delete_buffer(&self, buffer: GLuint);
+
+    fn create_vertex_array(&self) -> GLuint;
+    fn bind_vertex_array(&self, vao: GLuint);
+    fn delete_vertex_array(&self, vao: GLuint);
+    fn get_attrib_location(&self, program: GLuint, name: &str) -> GLint;
+    fn enable_vertex_attrib_array(&self, index: GLuint);
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, ty: GLenum, normalized: bool, stride: GLint, offset: usize);
+    fn vertex_attrib_i_pointer(&self, index: GLuint, size: GLint, ty: GLenum, stride: GLint, offset: usize);
+
+    fn create_texture(&self) -> GLuint;
+    fn active_texture(&self, unit: GLuint);
+    fn bind_texture(&self, target: GLenum, texture: GLuint);
+    fn tex_image_2d(&self, target: GLenum, internal_format: GLint, width: u32, height: u32, format: GLenum, ty: GLenum, data: &[u8]);
+    fn tex_sub_image_2d(&self, target: GLenum, x: u32, y: u32, width: u32, height: u32, format: GLenum, ty: GLenum, data: &[u8]);
+    fn tex_parameter(&self, target: GLenum, pname: GLenum, param: GLint);
+    fn tex_parameter_f(&self, target: GLenum, pname: GLenum, param: f32);
+    fn generate_mipmap(&self, target: GLenum);
+    fn delete_texture(&self, texture: GLuint);
+
+    fn create_framebuffer(&self) -> GLuint;
+    fn bind_framebuffer(&self, target: GLenum, framebuffer: GLuint);
+    fn create_renderbuffer(&self, internal_format: GLenum, width: u32, height: u32) -> GLuint;
+    fn framebuffer_renderbuffer(&self, attachment: GLenum, renderbuffer: GLuint);
+    fn check_framebuffer_status(&self) -> GLenum;
+    fn delete_framebuffer(&self, framebuffer: GLuint);
+    fn delete_renderbuffer(&self, renderbuffer: GLuint);
+
+    fn compile_shader(&self, source: &str, ty: GLenum) -> Result<GLuint, String>;
+    fn delete_shader(&self, shader: GLuint);
+    fn link_program(&self, shaders: &[GLuint]) -> Result<GLuint, String>;
+    /// Inputs the compiler removed are not included.
+    fn active_attributes(&self, program: GLuint) -> Vec<ActiveVariable>;
+    fn active_uniforms(&self, program: GLuint) -> Vec<ActiveVariable>;
+    fn delete_program(&self, program: GLuint);
+    fn use_program(&self, program: GLuint);
+    fn bind_frag_data_location(&self, program: GLuint, color_number: GLuint, name: &str);
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint;
+    fn uniform_1i(&self, location: GLint, value: GLint);
+    fn uniform_1f(&self, location: GLint, value: f32);
+    fn uniform_3f(&self, location: GLint, x: f32, y: f32, z: f32);
+    fn uniform_matrix_4fv(&self, location: GLint, value: &[f32; 16]);
+
+    fn enable(&self, cap: GLenum);
+    fn disable(&self, cap: GLenum);
+    fn depth_mask(&self, flag: bool);
+    fn depth_func(&self, func: GLenum);
+    fn blend_func(&self, sfactor: GLenum, dfactor: GLenum);
+    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32);
+    fn clear(&self, mask: GLbitfield);
+    fn draw_elements(&self, mode: GLenum, count: GLint, ty: GLenum, offset: usize);
+    /// Rows are tightly packed, bottom row first.
+    fn read_pixels(&self, width: u32, height: u32, format: GLenum, ty: GLenum, data: &mut [u8]);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveVariable {
+    pub name: String,
+    pub ty: GLenum,
+    pub size: GLint,
+    pub location: GLint
+}
+
+pub fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>()) }
+}
+
+fn gl_bool(value: bool) -> GLboolean {
+    if value { gl::TRUE } else { gl::FALSE }
+}
+
+#[derive(Debug)]
+pub struct GLBackend;
+
+type GetActiveFn = unsafe fn(GLuint, GLuint, GLsizei, *mut GLsizei, *mut GLint, *mut GLenum, *mut GLchar);
+
+unsafe fn active_variables(program: GLuint, count_pname: GLenum, max_length_pname: GLenum, get_active: GetActiveFn) -> Vec<(String, GLenum, GLint)> {
+    let mut count = 0;
+    gl::GetProgramiv(program, count_pname, &mut count);
+    let mut max_length = 0;
+    gl::GetProgramiv(program, max_length_pname, &mut max_length);
+    (0..count as GLuint).map(|index| {
+        let mut buf: Vec<u8> = vec![0; cmp::max(max_length, 1) as usize];
+        let mut length = 0;
+        let mut size = 0;
+        let mut ty = 0;
+        get_active(program, index, max_length, &mut length, &mut size, &mut ty, buf.as_mut_ptr() as *mut GLchar);
+        buf.truncate(length as usize);
+        (String::from_utf8(buf).ok().expect("Active variable name not valid utf8"), ty, size)
+    }).collect()
+}
+
+impl RenderBackend for GLBackend {
+    fn create_buffer(&self, target: GLenum, data: &[u8], usage: GLenum) -> GLuint {
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(target, buffer);
+            gl::BufferData(target, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid, usage);
+        }
+        buffer
+    }
+    fn bind_buffer(&self, target: GLenum, buffer: GLuint) {
+        unsafe { gl::BindBuffer(target, buffer) };
+    }
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum) {
+        unsafe { gl::BufferData(target, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid, usage) };
+    }
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]) {
+        unsafe { gl::BufferSubData(target, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid) };
+    }
+    fn delete_buffer(&self, buffer: GLuint) {
+        unsafe { gl::DeleteBuffers(1, &buffer) };
+    }
+
+    fn create_vertex_array(&self) -> GLuint {
+        let mut vao = 0;
+        unsafe { gl::GenVertexArrays(1, &mut vao) };
+        vao
+    }
+    fn bind_vertex_array(&self, vao: GLuint) {
+        unsafe { gl::BindVertexArray(vao) };
+    }
+    fn delete_vertex_array(&self, vao: GLuint) {
+        unsafe { gl::DeleteVertexArrays(1, &vao) };
+    }
+    fn get_attrib_location(&self, program: GLuint, name: &str) -> GLint {
+        unsafe { gl::GetAttribLocation(program, CString::new(name).unwrap().as_ptr()) }
+    }
+    fn enable_vertex_attrib_array(&self, index: GLuint) {
+        unsafe { gl::EnableVertexAttribArray(index) };
+    }
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, ty: GLenum, normalized: bool, stride: GLint, offset: usize) {
+        unsafe { gl::VertexAttribPointer(index, size, ty, gl_bool(normalized), stride, offset as *const GLvoid) };
+    }
+    fn vertex_attrib_i_pointer(&self, index: GLuint, size: GLint, ty: GLenum, stride: GLint, offset: usize) {
+        unsafe { gl::VertexAttribIPointer(index, size, ty, stride, offset as *const GLvoid) };
+    }
+
+    fn create_texture(&self) -> GLuint {
+        let mut texture = 0;
+        unsafe { gl::GenTextures(1, &mut texture) };
+        texture
+    }
+    fn active_texture(&self, unit: GLuint) {
+        unsafe { gl::ActiveTexture(gl::TEXTURE0 + unit) };
+    }
+    fn bind_texture(&self, target: GLenum, texture: GLuint) {
+        unsafe { gl::BindTexture(target, texture) };
+    }
+    fn tex_image_2d(&self, target: GLenum, internal_format: GLint, width: u32, height: u32, format: GLenum, ty: GLenum, data: &[u8]) {
+        unsafe {
+            gl::TexImage2D(target, 0, internal_format, width as GLint, height as GLint, 0,
+                format, ty, data.as_ptr() as *const GLvoid);
+        }
+    }
+    fn tex_sub_image_2d(&self, target: GLenum, x: u32, y: u32, width: u32, height: u32, format: GLenum, ty: GLenum, data: &[u8]) {
+        unsafe {
+            gl::TexSubImage2D(target, 0, x as GLint, y as GLint, width as GLsizei, height as GLsizei,
+                format, ty, data.as_ptr() as *const GLvoid);
+        }
+    }
+    fn tex_parameter(&self, target: GLenum, pname: GLenum, param: GLint) {
+        unsafe { gl::TexParameteri(target, pname, param) };
+    }
+    fn tex_parameter_f(&self, target: GLenum, pname: GLenum, param: f32) {
+        unsafe { gl::TexParameterf(target, pname, param) };
+    }
+    fn generate_mipmap(&self, target: GLenum) {
+        unsafe { gl::GenerateMipmap(target) };
+    }
+    fn delete_texture(&self, texture: GLuint) {
+        unsafe { gl::DeleteTextures(1, &texture) };
+    }
+
+    fn create_framebuffer(&self) -> GLuint {
+        let mut framebuffer = 0;
+        unsafe { gl::GenFramebuffers(1, &mut framebuffer) };
+        framebuffer
+    }
+    fn bind_framebuffer(&self, target: GLenum, framebuffer: GLuint) {
+        unsafe { gl::BindFramebuffer(target, framebuffer) };
+    }
+    fn create_renderbuffer(&self, internal_format: GLenum, width: u32, height: u32) -> GLuint {
+        let mut renderbuffer = 0;
+        unsafe {
+            gl::GenRenderbuffers(1, &mut renderbuffer);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, internal_format, width as GLint, height as GLint);
+        }
+        renderbuffer
+    }
+    fn framebuffer_renderbuffer(&self, attachment: GLenum, renderbuffer: GLuint) {
+        unsafe { gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, renderbuffer) };
+    }
+    fn check_framebuffer_status(&self) -> GLenum {
+        unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) }
+    }
+    fn delete_framebuffer(&self, framebuffer: GLuint) {
+        unsafe { gl::DeleteFramebuffers(1, &framebuffer) };
+    }
+    fn delete_renderbuffer(&self, renderbuffer: GLuint) {
+        unsafe { gl::DeleteRenderbuffers(1, &renderbuffer) };
+    }
+
+    fn compile_shader(&self, source: &str, ty: GLenum) -> Result<GLuint, String> {
+        unsafe {
+            let shader = gl::CreateShader(ty);
+            // Attempt to compile the shader
+            let c_str = CString::new(source.as_bytes()).unwrap();
+            gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
+            gl::CompileShader(shader);
+
+            // Get the compile status
+            let mut status = gl::FALSE as GLint;
+            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+
+            if status != (gl::TRUE as GLint) {
+                let mut len = 0;
+                gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
+                let mut buf = Vec::with_capacity(len as usize);
+                buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
+                gl::GetShaderInfoLog(shader, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
+                gl::DeleteShader(shader);
+                return Err(str::from_utf8(&buf).ok().expect("ShaderInfoLog not valid utf8").to_string());
+            }
+            Ok(shader)
+        }
+    }
+    fn delete_shader(&self, shader: GLuint) {
+        unsafe { gl::DeleteShader(shader) };
+    }
+    fn link_program(&self, shaders: &[GLuint]) -> Result<GLuint, String> {
+        unsafe {
+            let program = gl::CreateProgram();
+            for shader in shaders {
+                gl::AttachShader(program, *shader);
+            }
+            gl::LinkProgram(program);
+            // Get the link status
+            let mut status = gl::FALSE as GLint;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+
+            if status != (gl::TRUE as GLint) {
+                let mut len: GLint = 0;
+                gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
+                let mut buf = Vec::with_capacity(len as usize);
+                buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
+                gl::GetProgramInfoLog(program, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
+                gl::DeleteProgram(program);
+                return Err(str::from_utf8(&buf).ok().expect("ProgramInfoLog not valid utf8").to_string());
+            }
+            Ok(program)
+        }
+    }
+    fn active_attributes(&self, program: GLuint) -> Vec<ActiveVariable> {
+        let variables = unsafe { active_variables(program, gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, gl::GetActiveAttrib) };
+        variables.into_iter().map(|(name, ty, size)| ActiveVariable {
+            location: self.get_attrib_location(program, &name),
+            name: name,
+            ty: ty,
+            size: size
+        }).collect()
+    }
+    fn active_uniforms(&self, program: GLuint) -> Vec<ActiveVariable> {
+        let variables = unsafe { active_variables(program, gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH, gl::GetActiveUniform) };
+        variables.into_iter().map(|(name, ty, size)| ActiveVariable {
+            location: self.get_uniform_location(program, &name),
+            name: name,
+            ty: ty,
+            size: size
+        }).collect()
+    }
+    fn delete_program(&self, program: GLuint) {
+        unsafe { gl::DeleteProgram(program) };
+    }
+    fn use_program(&self, program: GLuint) {
+        unsafe { gl::UseProgram(program) };
+    }
+    fn bind_frag_data_location(&self, program: GLuint, color_number: GLuint, name: &str) {
+        unsafe { gl::BindFragDataLocation(program, color_number, CString::new(name).unwrap().as_ptr()) };
+    }
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        unsafe { gl::GetUniformLocation(program, CString::new(name).unwrap().as_ptr()) }
+    }
+    fn uniform_1i(&self, location: GLint, value: GLint) {
+        unsafe { gl::Uniform1i(location, value) };
+    }
+    fn uniform_1f(&self, location: GLint, value: f32) {
+        unsafe { gl::Uniform1f(location, value) };
+    }
+    fn uniform_3f(&self, location: GLint, x: f32, y: f32, z: f32) {
+        unsafe { gl::Uniform3f(location, x, y, z) };
+    }
+    fn uniform_matrix_4fv(&self, location: GLint, value: &[f32; 16]) {
+        unsafe { gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr()) };
+    }
+
+    fn enable(&self, cap: GLenum) {
+        unsafe { gl::Enable(cap) };
+    }
+    fn disable(&self, cap: GLenum) {
+        unsafe { gl::Disable(cap) };
+    }
+    fn depth_mask(&self, flag: bool) {
+        unsafe { gl::DepthMask(gl_bool(flag)) };
+    }
+    fn depth_func(&self, func: GLenum) {
+        unsafe { gl::DepthFunc(func) };
+    }
+    fn blend_func(&self, sfactor: GLenum, dfactor: GLenum) {
+        unsafe { gl::BlendFunc(sfactor, dfactor) };
+    }
+    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
+        unsafe { gl::ClearColor(r, g, b, a) };
+    }
+    fn clear(&self, mask: GLbitfield) {
+        unsafe { gl::Clear(mask) };
+    }
+    fn draw_elements(&self, mode: GLenum, count: GLint, ty: GLenum, offset: usize) {
+        unsafe { gl::DrawElements(mode, count, ty, offset as *const GLvoid) };
+    }
+    fn read_pixels(&self, width: u32, height: u32, format: GLenum, ty: GLenum, data: &mut [u8]) {
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(0, 0, width as GLint, height as GLint, format, ty, data.as_mut_ptr() as *mut GLvoid);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformValue {
+    Int(GLint),
+    Float(f32),
+    Vec3(f32, f32, f32),
+    Matrix4([f32; 16])
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderCommand {
+    CreateBuffer { buffer: GLuint, target: GLenum, size: usize, usage: GLenum },
+    BindBuffer { target: GLenum, buffer: GLuint },
+    BufferData { target: GLenum, size: usize, usage: GLenum },
+    BufferSubData { target: GLenum, offset: usize, size: usize },
+    DeleteBuffer(GLuint),
+    CreateVertexArray(GLuint),
+    BindVertexArray(GLuint),
+    DeleteVertexArray(GLuint),
+    EnableVertexAttribArray(GLuint),
+    VertexAttribPointer { index: GLuint, size: GLint, ty: GLenum, normalized: bool, stride: GLint, offset: usize },
+    VertexAttribIPointer { index: GLuint, size: GLint, ty: GLenum, stride: GLint, offset: usize },
+    CreateTexture(GLuint),
+    ActiveTexture(GLuint),
+    BindTexture { target: GLenum, texture: GLuint },
+    TexImage2D { target: GLenum, internal_format: GLint, width: u32, height: u32, format: GLenum, ty: GLenum },
+    TexSubImage2D { target: GLenum, x: u32, y: u32, width: u32, height: u32, format: GLenum, ty: GLenum },
+    TexParameter { target: GLenum, pname: GLenum, param: GLint },
+    TexParameterF { target: GLenum, pname: GLenum, param: f32 },
+    GenerateMipmap(GLenum),
+    DeleteTexture(GLuint),
+    CreateFramebuffer(GLuint),
+    BindFramebuffer { target: GLenum, framebuffer: GLuint },
+    CreateRenderbuffer { renderbuffer: GLuint, internal_format: GLenum, width: u32, height: u32 },
+    FramebufferRenderbuffer { attachment: GLenum, renderbuffer: GLuint },
+    DeleteFramebuffer(GLuint),
+    DeleteRenderbuffer(GLuint),
+    CompileShader { shader: GLuint, ty: GLenum },
+    DeleteShader(GLuint),
+    LinkProgram { program: GLuint, shaders: Vec<GLuint> },
+    DeleteProgram(GLuint),
+    UseProgram(GLuint),
+    SetUniform { program: GLuint, name: String, value: UniformValue },
+    Enable(GLenum),
+    Disable(GLenum),
+    DepthMask(bool),
+    DepthFunc(GLenum),
+    BlendFunc(GLenum, GLenum),
+    ClearColor(f32, f32, f32, f32),
+    Clear(GLbitfield),
+    DrawElements { mode: GLenum, count: GLint, ty: GLenum, offset: usize },
+    ReadPixels { width: u32, height: u32, format: GLenum, ty: GLenum }
+}
+
+/// Records every command instead of making GL calls. Active attributes and uniforms are read from
+/// the `in` and `uniform` lines of the shader sources.
+#[derive(Debug)]
+pub struct RecordingBackend {
+    commands: RefCell<Vec<RenderCommand>>,
+    next_name: Cell<GLuint>,
+    uniform_locations: RefCell<Vec<(GLuint, String)>>,
+    attrib_locations: RefCell<Vec<(GLuint, String)>>,
+    current_program: Cell<GLuint>,
+    read_pixels_data: RefCell<Vec<u8>>,
+    shader_sources: RefCell<Vec<(GLuint, GLenum, String)>>,
+    program_shaders: RefCell<Vec<(GLuint, Vec<GLuint>)>>
+}
+
+impl RecordingBackend {
+    pub fn new() -> RecordingBackend {
+        RecordingBackend {
+            commands: RefCell::new(vec![]),
+            next_name: Cell::new(1),
+            uniform_locations: RefCell::new(vec![]),
+            attrib_locations: RefCell::new(vec![]),
+            current_program: Cell::new(0),
+            read_pixels_data: RefCell::new(vec![]),
+            shader_sources: RefCell::new(vec![]),
+            program_shaders: RefCell::new(vec![])
+        }
+    }
+    pub fn commands(&self) -> Vec<RenderCommand> {
+        self.commands.borrow().clone()
+    }
+    pub fn set_read_pixels(&self, data: Vec<u8>) {
+        *self.read_pixels_data.borrow_mut() = data;
+    }
+    pub fn clear_commands(&self) {
+        self.commands.borrow_mut().clear();
+    }
+    fn record(&self, command: RenderCommand) {
+        self.commands.borrow_mut().push(command);
+    }
+    fn gen_name(&self) -> GLuint {
+        let name = self.next_name.get();
+        self.next_name.set(name + 1);
+        name
+    }
+    fn location(locations: &RefCell<Vec<(GLuint, String)>>, program: GLuint, name: &str) -> GLint {
+        let mut locations = locations.borrow_mut();
+        match locations.iter().position(|&(p, ref n)| p == program && n == name) {
+            Some(i) => i as GLint,
+            None => {
+                locations.push((program, name.to_string()));
+                (locations.len() - 1) as GLint
+            }
+        }
+    }
+    fn declarations(&self, program: GLuint, qualifier: &str, stage: Option<GLenum>) -> Vec<(String, GLenum)> {
+        let program_shaders = self.program_shaders.borrow();
+        let shaders = match program_shaders.iter().find(|&&(p, _)| p == program) {
+            Some(&(_, ref shaders)) => shaders,
+            None => return vec![]
+        };
+        let mut declarations = vec![];
+        for &(shader, ty, ref source) in self.shader_sources.borrow().iter() {
+            if !shaders.contains(&shader) || stage.map(|stage| stage != ty).unwrap_or(false) {
+                continue;
+            }
+            for line in source.lines() {
+                let words: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ';').filter(|w| w.len() > 0).collect();
+                if words.len() < 3 || words[0] != qualifier {
+                    continue;
+                }
+                if let Some(ty) = glsl_type(words[1]) {
+                    if !declarations.iter().any(|&(ref name, _)| name == words[2]) {
+                        declarations.push((words[2].to_string(), ty));
+                    }
+                }
+            }
+        }
+        declarations
+    }
+    fn set_uniform(&self, location: GLint, value: UniformValue) {
+        let (program, name) = if location < 0 {
+            (self.current_program.get(), "".to_string())
+        } else {
+            self.uniform_locations.borrow()[location as usize].clone()
+        };
+        self.record(RenderCommand::SetUniform { program: program, name: name, value: value });
+    }
+}
+
+impl RenderBackend for RecordingBackend {
+    fn create_buffer(&self, target: GLenum, data: &[u8], usage: GLenum) -> GLuint {
+        let buffer = self.gen_name();
+        self.record(RenderCommand::CreateBuffer { buffer: buffer, target: target, size: data.len(), usage: usage });
+        buffer
+    }
+    fn bind_buffer(&self, target: GLenum, buffer: GLuint) {
+        self.record(RenderCommand::BindBuffer { target: target, buffer: buffer });
+    }
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum) {
+        self.record(RenderCommand::BufferData { target: target, size: data.len(), usage: usage });
+    }
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]) {
+        self.record(RenderCommand::BufferSubData { target: target, offset: offset, size: data.len() });
+    }
+    fn delete_buffer(&self, buffer: GLuint) {
+        self.record(RenderCommand::DeleteBuffer(buffer));
+    }
+
+    fn create_vertex_array(&self) -> GLuint {
+        let vao = self.gen_name();
+        self.record(RenderCommand::CreateVertexArray(vao));
+        vao
+    }
+    fn bind_vertex_array(&self, vao: GLuint) {
+        self.record(RenderCommand::BindVertexArray(vao));
+    }
+    fn delete_vertex_array(&self, vao: GLuint) {
+        self.record(RenderCommand::DeleteVertexArray(vao));
+    }
+    fn get_attrib_location(&self, program: GLuint, name: &str) -> GLint {
+        RecordingBackend::location(&self.attrib_locations, program, name)
+    }
+    fn enable_vertex_attrib_array(&self, index: GLuint) {
+        self.record(RenderCommand::EnableVertexAttribArray(index));
+    }
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, ty: GLenum, normalized: bool, stride: GLint, offset: usize) {
+        self.record(RenderCommand::VertexAttribPointer { index: index, size: size, ty: ty, normalized: normalized, stride: stride, offset: offset });
+    }
+    fn vertex_attrib_i_pointer(&self, index: GLuint, size: GLint, ty: GLenum, stride: GLint, offset: usize) {
+        self.record(RenderCommand::VertexAttribIPointer { index: index, size: size, ty: ty, stride: stride, offset: offset });
+    }
+
+    fn create_texture(&self) -> GLuint {
+        let texture = self.gen_name();
+        self.record(RenderCommand::CreateTexture(texture));
+        texture
+    }
+    fn active_texture(&self, unit: GLuint) {
+        self.record(RenderCommand::ActiveTexture(unit));
+    }
+    fn bind_texture(&self, target: GLenum, texture: GLuint) {
+        self.record(RenderCommand::BindTexture { target: target, texture: texture });
+    }
+    fn tex_image_2d(&self, target: GLenum, internal_format: GLint, width: u32, height: u32, format: GLenum, ty: GLenum, data: &[u8]) {
+        self.record(RenderCommand::TexImage2D { target: target, internal_format: internal_format, width: width, height: height, format: format, ty: ty });
+    }
+    fn tex_sub_image_2d(&self, target: GLenum, x: u32, y: u32, width: u32, height: u32, format: GLenum, ty: GLenum, data: &[u8]) {
+        self.record(RenderCommand::TexSubImage2D { target: target, x: x, y: y, width: width, height: height, format: format, ty: ty });
+    }
+    fn tex_parameter(&self, target: GLenum, pname: GLenum, param: GLint) {
+        self.record(RenderCommand::TexParameter { target: target, pname: pname, param: param });
+    }
+    fn tex_parameter_f(&self, target: GLenum, pname: GLenum, param: f32) {
+        self.record(RenderCommand::TexParameterF { target: target, pname: pname, param: param });
+    }
+    fn generate_mipmap(&self, target: GLenum) {
+        self.record(RenderCommand::GenerateMipmap(target));
+    }
+    fn delete_texture(&self, texture: GLuint) {
+        self.record(RenderCommand::DeleteTexture(texture));
+    }
+
+    fn create_framebuffer(&self) -> GLuint {
+        let framebuffer = self.gen_name();
+        self.record(RenderCommand::CreateFramebuffer(framebuffer));
+        framebuffer
+    }
+    fn bind_framebuffer(&self, target: GLenum, framebuffer: GLuint) {
+        self.record(RenderCommand::BindFramebuffer { target: target, framebuffer: framebuffer });
+    }
+    fn create_renderbuffer(&self, internal_format: GLenum, width: u32, height: u32) -> GLuint {
+        let renderbuffer = self.gen_name();
+        self.record(RenderCommand::CreateRenderbuffer { renderbuffer: renderbuffer, internal_format: internal_format, width: width, height: height });
+        renderbuffer
+    }
+    fn framebuffer_renderbuffer(&self, attachment: GLenum, renderbuffer: GLuint) {
+        self.record(RenderCommand::FramebufferRenderbuffer { attachment: attachment, renderbuffer: renderbuffer });
+    }
+    fn check_framebuffer_status(&self) -> GLenum {
+        gl::FRAMEBUFFER_COMPLETE
+    }
+    fn delete_framebuffer(&self, framebuffer: GLuint) {
+        self.record(RenderCommand::DeleteFramebuffer(framebuffer));
+    }
+    fn delete_renderbuffer(&self, renderbuffer: GLuint) {
+        self.record(RenderCommand::DeleteRenderbuffer(renderbuffer));
+    }
+
+    fn compile_shader(&self, source: &str, ty: GLenum) -> Result<GLuint, String> {
+        let shader = self.gen_name();
+        self.shader_sources.borrow_mut().push((shader, ty, source.to_string()));
+        self.record(RenderCommand::CompileShader { shader: shader, ty: ty });
+        Ok(shader)
+    }
+    fn delete_shader(&self, shader: GLuint) {
+        self.record(RenderCommand::DeleteShader(shader));
+    }
+    fn link_program(&self, shaders: &[GLuint]) -> Result<GLuint, String> {
+        let program = self.gen_name();
+        self.program_shaders.borrow_mut().push((program, shaders.to_vec()));
+        self.record(RenderCommand::LinkProgram { program: program, shaders: shaders.to_vec() });
+        Ok(program)
+    }
+    fn active_attributes(&self, program: GLuint) -> Vec<ActiveVariable> {
+        self.declarations(program, "in", Some(gl::VERTEX_SHADER)).into_iter().map(|(name, ty)| ActiveVariable {
+            location: self.get_attrib_location(program, &name),
+            name: name,
+            ty: ty,
+            size: 1
+        }).collect()
+    }
+    fn active_uniforms(&self, program: GLuint) -> Vec<ActiveVariable> {
+        self.declarations(program, "uniform", None).into_iter().map(|(name, ty)| ActiveVariable {
+            location: self.get_uniform_location(program, &name),
+            name: name,
+            ty: ty,
+            size: 1
+        }).collect()
+    }
+    fn delete_program(&self, program: GLuint) {
+        self.record(RenderCommand::DeleteProgram(program));
+    }
+    fn use_program(&self, program: GLuint) {
+        self.current_program.set(program);
+        self.record(RenderCommand::UseProgram(program));
+    }
+    fn bind_frag_data_location(&self, program: GLuint, color_number: GLuint, name: &str) {
+    }
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        RecordingBackend::location(&self.uniform_locations, program, name)
+    }
+    fn uniform_1i(&self, location: GLint, value: GLint) {
+        self.set_uniform(location, UniformValue::Int(value));
+    }
+    fn uniform_1f(&self, location: GLint, value: f32) {
+        self.set_uniform(location, UniformValue::Float(value));
+    }
+    fn uniform_3f(&self, location: GLint, x: f32, y: f32, z: f32) {
+        self.set_uniform(location, UniformValue::Vec3(x, y, z));
+    }
+    fn uniform_matrix_4fv(&self, location: GLint, value: &[f32; 16]) {
+        self.set_uniform(location, UniformValue::Matrix4(*value));
+    }
+
+    fn enable(&self, cap: GLenum) {
+        self.record(RenderCommand::Enable(cap));
+    }
+    fn disable(&self, cap: GLenum) {
+        self.record(RenderCommand::Disable(cap));
+    }
+    fn depth_mask(&self, flag: bool) {
+        self.record(RenderCommand::DepthMask(flag));
+    }
+    fn depth_func(&self, func: GLenum) {
+        self.record(RenderCommand::DepthFunc(func));
+    }
+    fn blend_func(&self, sfactor: GLenum, dfactor: GLenum) {
+        self.record(RenderCommand::BlendFunc(sfactor, dfactor));
+    }
+    fn clear_color(&self, r: f32, g: f32, b: f32, a: f32) {
+        self.record(RenderCommand::ClearColor(r, g, b, a));
+    }
+    fn clear(&self, mask: GLbitfield) {
+        self.record(RenderCommand::Clear(mask));
+    }
+    fn draw_elements(&self, mode: GLenum, count: GLint, ty: GLenum, offset: usize) {
+        self.record(RenderCommand::DrawElements { mode: mode, count: count, ty: ty, offset: offset });
+    }
+    fn read_pixels(&self, width: u32, height: u32, format: GLenum, ty: GLenum, data: &mut [u8]) {
+        self.record(RenderCommand::ReadPixels { width: width, height: height, format: format, ty: ty });
+        let stub = self.read_pixels_data.borrow();
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = stub.get(i).cloned().unwrap_or(0);
+        }
+    }
+}
+
+fn glsl_type(name: &str) -> Option<GLenum> {
+    Some(match name {
+        "float" => gl::FLOAT,
+        "vec2" => gl::FLOAT_VEC2,
+        "vec3" => gl::FLOAT_VEC3,
+        "vec4" => gl::FLOAT_VEC4,
+        "int" => gl::INT,
+        "ivec2" => gl::INT_VEC2,
+        "ivec3" => gl::INT_VEC3,
+        "ivec4" => gl::INT_VEC4,
+        "uint" => gl::UNSIGNED_INT,
+        "uvec2" => gl::UNSIGNED_INT_VEC2,
+        "uvec3" => gl::UNSIGNED_INT_VEC3,
+        "uvec4" => gl::UNSIGNED_INT_VEC4,
+        "bool" => gl::BOOL,
+        "mat3" => gl::FLOAT_MAT3,
+        "mat4" => gl::FLOAT_MAT4,
+        "sampler2D" => gl::SAMPLER_2D,
+        "samplerCube" => gl::SAMPLER_CUBE,
+        _ => return None
+    })
+}
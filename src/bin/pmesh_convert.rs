@@ -0,0 +1,79 @@
+//! Converts a mesh to the binary `.pmesh` format.
+//!
+//!     pmesh_convert <input> <output.pmesh>
+//!
+//! The input is either a mesh file that `mesh_from_file` understands (such as `.obj`), or a `.pon`
+//! file holding a mesh description such as a `static_mesh`. Its `process` steps are applied before
+//! writing; meshes with a `topology` or packed component types in `layout` are rejected, since the
+//! format stores neither.
+
+extern crate pyramid;
+extern crate pyramid_viewport;
+extern crate ppromise;
+extern crate mesh;
+
+use pyramid::pon::*;
+use pyramid_viewport::pmesh::*;
+use pyramid_viewport::pon_to_resource::*;
+use ppromise::*;
+use mesh::*;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use std::thread;
+
+fn load_pon_mesh(path: &Path) -> Result<Rc<Mesh>, String> {
+    let mut source = String::new();
+    try!(File::open(path).and_then(|mut f| f.read_to_string(&mut source)).map_err(|err| format!("{:?}", err)));
+    let pon = try!(Pon::from_string(&source).map_err(|err| format!("{:?}", err)));
+    let root_path = path.parent().unwrap_or(Path::new(""));
+    let mut loadable = try!(pon_to_mesh(root_path, &pon, &mut TranslateContext::empty()).map_err(|err| format!("{:?}", err)));
+    if loadable.topology() != MeshTopology::Triangles {
+        return Err(format!("the topology {:?} can not be stored in a pmesh file", loadable.topology()));
+    }
+    if loadable.vertex_format().0.len() > 0 {
+        return Err(format!("the component types {:?} can not be stored in a pmesh file", loadable.vertex_format().0));
+    }
+    let mut async_runner = AsyncRunner::new_pooled(1);
+    let promise = loadable.load(&mut async_runner);
+    loop {
+        async_runner.try_resolve_all();
+        if promise.value().is_some() {
+            break;
+        }
+        thread::yield_now();
+    }
+    promise.into_value().map_err(|err| err.to_string())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        println!("Usage: {} <input> <output.pmesh>", args[0]);
+        process::exit(1);
+    }
+    let input = Path::new(&args[1]);
+    let mesh = if input.extension().and_then(|ext| ext.to_str()) == Some("pon") {
+        load_pon_mesh(input)
+    } else {
+        mesh_from_file(input, None).map(|mesh| Rc::new(mesh)).map_err(|err| err.to_string())
+    };
+    let mesh = match mesh {
+        Ok(mesh) => mesh,
+        Err(err) => {
+            println!("Failed to load {:?}: {}", input, err);
+            process::exit(1);
+        }
+    };
+    let result = File::create(&args[2]).and_then(|file| write_pmesh(&mesh, &mut BufWriter::new(file)));
+    if let Err(err) = result {
+        println!("Failed to write {}: {:?}", args[2], err);
+        process::exit(1);
+    }
+    println!("Wrote {} vertex floats and {} indices to {}", mesh.vertex_data.len(), mesh.element_data.len(), args[2]);
+}
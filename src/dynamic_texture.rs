@@ -0,0 +1,109 @@
+//! Textures whose pixels are updated at runtime, referenced with `dynamic_texture "<id>"`.
+
+use image::RgbaImage;
+use std::cell::RefCell;
+use std::cmp;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl DirtyRect {
+    fn union(&self, other: &DirtyRect) -> DirtyRect {
+        let x = cmp::min(self.x, other.x);
+        let y = cmp::min(self.y, other.y);
+        DirtyRect {
+            x: x,
+            y: y,
+            width: cmp::max(self.x + self.width, other.x + other.width) - x,
+            height: cmp::max(self.y + self.height, other.y + other.height) - y
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DynamicTextureData {
+    image: RgbaImage,
+    dirty: Option<DirtyRect>
+}
+
+/// Clones refer to the same image.
+#[derive(Debug, Clone)]
+pub struct DynamicTexture(Rc<RefCell<DynamicTextureData>>);
+
+impl DynamicTexture {
+    pub fn new(image: RgbaImage) -> DynamicTexture {
+        DynamicTexture(Rc::new(RefCell::new(DynamicTextureData {
+            image: image,
+            dirty: None
+        })))
+    }
+    pub fn width(&self) -> u32 {
+        self.0.borrow().image.width()
+    }
+    pub fn height(&self) -> u32 {
+        self.0.borrow().image.height()
+    }
+    /// The size of a dynamic texture can not change.
+    pub fn set_image(&self, image: RgbaImage) -> Result<(), String> {
+        let mut data = self.0.borrow_mut();
+        if image.dimensions() != data.image.dimensions() {
+            return Err(format!("Expected a {:?} image, got {:?}", data.image.dimensions(), image.dimensions()));
+        }
+        let (width, height) = image.dimensions();
+        data.image = image;
+        data.mark_dirty(DirtyRect { x: 0, y: 0, width: width, height: height });
+        Ok(())
+    }
+    pub fn update_region(&self, x: u32, y: u32, image: &RgbaImage) -> Result<(), String> {
+        let mut data = self.0.borrow_mut();
+        let inside = match (x.checked_add(image.width()), y.checked_add(image.height())) {
+            (Some(right), Some(bottom)) => right <= data.image.width() && bottom <= data.image.height(),
+            _ => false
+        };
+        if !inside {
+            return Err(format!("Region {:?} at {}, {} is outside of the texture", image.dimensions(), x, y));
+        }
+        for (ix, iy, pixel) in image.enumerate_pixels() {
+            data.image.put_pixel(x + ix, y + iy, *pixel);
+        }
+        data.mark_dirty(DirtyRect { x: x, y: y, width: image.width(), height: image.height() });
+        Ok(())
+    }
+    pub fn same_texture(&self, other: &DynamicTexture) -> bool {
+        &*self.0 as *const RefCell<DynamicTextureData> == &*other.0 as *const RefCell<DynamicTextureData>
+    }
+    pub fn image(&self) -> RgbaImage {
+        self.0.borrow().image.clone()
+    }
+    pub fn take_dirty(&self) -> Option<(DirtyRect, RgbaImage)> {
+        let mut data = self.0.borrow_mut();
+        match data.dirty.take() {
+            Some(rect) => {
+                let mut region = RgbaImage::new(rect.width, rect.height);
+                for (x, y, pixel) in region.enumerate_pixels_mut() {
+                    *pixel = *data.image.get_pixel(rect.x + x, rect.y + y);
+                }
+                Some((rect, region))
+            },
+            None => None
+        }
+    }
+}
+
+impl DynamicTextureData {
+    fn mark_dirty(&mut self, rect: DirtyRect) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => dirty.union(&rect),
+            None => rect
+        });
+    }
+}
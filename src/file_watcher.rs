@@ -0,0 +1,85 @@
+extern crate time;
+
+use pyramid::pon::*;
+
+use time::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+pub struct FileWatcher {
+    files: HashMap<PathBuf, WatchedFile>,
+    poll_interval: Duration,
+    last_poll: Timespec
+}
+
+struct WatchedFile {
+    modified: Option<Timespec>,
+    keys: Vec<Pon>
+}
+
+#[cfg(unix)]
+fn modified_time(path: &Path) -> Option<Timespec> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| Timespec::new(m.mtime(), m.mtime_nsec() as i32))
+}
+
+#[cfg(windows)]
+fn modified_time(path: &Path) -> Option<Timespec> {
+    use std::os::windows::fs::MetadataExt;
+    // In 100 nanosecond intervals
+    fs::metadata(path).ok().map(|m| {
+        let t = m.last_write_time();
+        Timespec::new((t / 10_000_000) as i64, ((t % 10_000_000) * 100) as i32)
+    })
+}
+
+impl FileWatcher {
+    pub fn new() -> FileWatcher {
+        FileWatcher {
+            files: HashMap::new(),
+            poll_interval: Duration::milliseconds(500),
+            last_poll: time::get_time()
+        }
+    }
+    pub fn watch(&mut self, path: &Path, key: &Pon) {
+        let file = self.files.entry(path.to_path_buf()).or_insert_with(|| WatchedFile {
+            modified: modified_time(path),
+            keys: vec![]
+        });
+        if !file.keys.contains(key) {
+            file.keys.push(key.clone());
+        }
+    }
+    pub fn unwatch(&mut self, key: &Pon) {
+        for file in self.files.values_mut() {
+            file.keys.retain(|k| k != key);
+        }
+        let empty: Vec<PathBuf> = self.files.iter().filter(|&(_, f)| f.keys.len() == 0).map(|(p, _)| p.clone()).collect();
+        for path in empty {
+            self.files.remove(&path);
+        }
+    }
+    /// Does nothing if called more often than the poll interval.
+    pub fn poll(&mut self) -> Vec<Pon> {
+        let now = time::get_time();
+        if now - self.last_poll < self.poll_interval {
+            return vec![];
+        }
+        self.last_poll = now;
+        let mut changed = vec![];
+        for (path, file) in self.files.iter_mut() {
+            let modified = modified_time(path);
+            if modified != file.modified {
+                println!("File changed: {:?}", path);
+                file.modified = modified;
+                for key in &file.keys {
+                    if !changed.contains(key) {
+                        changed.push(key.clone());
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
@@ -11,190 +11,615 @@ use std::ptr;
 use std::ffi::CString;
 use std::str;
 use std::rc::Rc;
+use std::cell::Cell;
+use byteorder::{NativeEndian, WriteBytesExt};
 
 use pon_to_resource::*;
+use backend::*;
 
+pub trait GPUResource {
+    fn size_bytes(&self) -> usize;
+    fn last_drawn(&self) -> u64;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GLAttribute {
+    pub name: String,
+    pub size: usize,
+    pub component_type: ComponentType,
+    pub offset: usize
+}
+
+impl GLAttribute {
+    pub fn gl_type(&self) -> GLenum {
+        match self.component_type {
+            ComponentType::Float => gl::FLOAT,
+            ComponentType::HalfFloat => gl::HALF_FLOAT,
+            ComponentType::UnsignedByteNormalized | ComponentType::UnsignedByte => gl::UNSIGNED_BYTE,
+            ComponentType::UnsignedShortNormalized | ComponentType::UnsignedShort => gl::UNSIGNED_SHORT,
+            ComponentType::Int => gl::INT
+        }
+    }
+    pub fn normalized(&self) -> bool {
+        self.component_type == ComponentType::UnsignedByteNormalized || self.component_type == ComponentType::UnsignedShortNormalized
+    }
+    pub fn integer(&self) -> bool {
+        match self.component_type {
+            ComponentType::UnsignedByte | ComponentType::UnsignedShort | ComponentType::Int => true,
+            _ => false
+        }
+    }
+    fn component_bytes(&self) -> usize {
+        match self.component_type {
+            ComponentType::Float | ComponentType::Int => 4,
+            ComponentType::HalfFloat | ComponentType::UnsignedShortNormalized | ComponentType::UnsignedShort => 2,
+            ComponentType::UnsignedByteNormalized | ComponentType::UnsignedByte => 1
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GLVertexLayout {
+    pub attributes: Vec<GLAttribute>,
+    pub stride: usize
+}
+
+impl GLVertexLayout {
+    pub fn new(layout: &Layout, format: &VertexFormat) -> GLVertexLayout {
+        let mut offset = 0;
+        let attributes = layout.attributes.iter().map(|attribute| {
+            let gl_attribute = GLAttribute {
+                name: attribute.name.clone(),
+                size: attribute.size,
+                component_type: format.component_type(&attribute.name),
+                offset: offset
+            };
+            offset += (gl_attribute.size * gl_attribute.component_bytes() + 3) / 4 * 4;
+            gl_attribute
+        }).collect();
+        GLVertexLayout {
+            attributes: attributes,
+            stride: offset
+        }
+    }
+    fn is_float(&self) -> bool {
+        self.attributes.iter().all(|a| a.component_type == ComponentType::Float)
+    }
+    pub fn pack(&self, layout: &Layout, vertex_data: &[f32]) -> Vec<u8> {
+        if self.is_float() {
+            return as_bytes(vertex_data).to_vec();
+        }
+        let n_vertices = if layout.stride == 0 { 0 } else { vertex_data.len() / layout.stride };
+        let mut data = vec![0; n_vertices * self.stride];
+        for vertex in 0..n_vertices {
+            for (attribute, gl_attribute) in layout.attributes.iter().zip(self.attributes.iter()) {
+                let values = &vertex_data[(vertex * layout.stride + attribute.offset)..(vertex * layout.stride + attribute.offset + attribute.size)];
+                let mut out = &mut data[(vertex * self.stride + gl_attribute.offset)..];
+                for &value in values {
+                    let written = match gl_attribute.component_type {
+                        ComponentType::Float => out.write_f32::<NativeEndian>(value),
+                        ComponentType::HalfFloat => out.write_u16::<NativeEndian>(f32_to_half(value)),
+                        ComponentType::UnsignedByteNormalized => out.write_u8((value.max(0.0).min(1.0) * 255.0).round() as u8),
+                        ComponentType::UnsignedShortNormalized => out.write_u16::<NativeEndian>((value.max(0.0).min(1.0) * 65535.0).round() as u16),
+                        ComponentType::UnsignedByte => out.write_u8(value.max(0.0).min(255.0).round() as u8),
+                        ComponentType::UnsignedShort => out.write_u16::<NativeEndian>(value.max(0.0).min(65535.0).round() as u16),
+                        ComponentType::Int => out.write_i32::<NativeEndian>(value.round() as i32)
+                    };
+                    // The buffer is sized for all attributes, so writing can not fail
+                    written.unwrap();
+                }
+            }
+        }
+        data
+    }
+}
 
-#[derive(Clone, Debug)]
+fn f32_to_half(value: f32) -> u16 {
+    let bits: u32 = unsafe { mem::transmute(value) };
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+    if exponent == 0xff {
+        // Infinity or NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal
+        let mantissa = mantissa | 0x800000;
+        let shift = (14 - exponent) as u32;
+        return sign | ((mantissa >> shift) + ((mantissa >> (shift - 1)) & 1)) as u16;
+    }
+    // A rounding carry into the exponent gives the right result
+    (((sign as u32) | ((exponent as u32) << 10) | (mantissa >> 13)) + ((mantissa >> 12) & 1)) as u16
+}
+
+/// Meshes whose indices all fit in 16 bits get 16-bit indices, so that out of range indices reach
+/// GL unchanged.
+fn index_type(mesh: &Mesh) -> GLenum {
+    if mesh.element_data.iter().all(|&i| i <= u16::max_value() as u32) {
+        gl::UNSIGNED_SHORT
+    } else {
+        gl::UNSIGNED_INT
+    }
+}
+
+fn pack_indices(element_data: &[u32], index_type: GLenum) -> Vec<u8> {
+    if index_type == gl::UNSIGNED_SHORT {
+        let indices: Vec<u16> = element_data.iter().map(|&i| i as u16).collect();
+        as_bytes(&indices).to_vec()
+    } else {
+        as_bytes(element_data).to_vec()
+    }
+}
+
+#[derive(Debug)]
 pub struct GLMesh {
+    backend: Rc<RenderBackend>,
     pub layout: Layout,
+    pub vertex_layout: GLVertexLayout,
     pub vbo: GLuint,
     pub ebo: GLuint,
-    pub nindices: GLint
+    pub mode: GLenum,
+    pub nindices: Cell<GLint>,
+    /// `UNSIGNED_SHORT` or `UNSIGNED_INT`. Can change when a dynamic mesh is updated.
+    pub index_type: Cell<GLenum>,
+    pub size_bytes: Cell<usize>,
+    pub last_drawn: Cell<u64>,
+    usage: GLenum,
+    // Sizes of the buffers in bytes, which can be larger than the current data of a dynamic mesh
+    vertex_capacity: Cell<usize>,
+    index_capacity: Cell<usize>
 }
 
 impl GLMesh {
-    pub fn new(mesh: &Mesh) -> GLMesh {
+    pub fn new(backend: &Rc<RenderBackend>, mesh: &Mesh) -> GLMesh {
+        GLMesh::with_format(backend, mesh, &VertexFormat::default(), MeshTopology::Triangles, gl::STATIC_DRAW)
+    }
+    pub fn new_dynamic(backend: &Rc<RenderBackend>, mesh: &Mesh) -> GLMesh {
+        GLMesh::with_format(backend, mesh, &VertexFormat::default(), MeshTopology::Triangles, gl::DYNAMIC_DRAW)
+    }
+    pub fn with_format(backend: &Rc<RenderBackend>, mesh: &Mesh, format: &VertexFormat, topology: MeshTopology, usage: GLenum) -> GLMesh {
         println!("Loading GL mesh into memory");
-        let mut vbo = 0;
-        let mut ebo = 0;
-
-        unsafe {
-            // Create a Vertex Buffer Object and copy the vertex data to it
-            gl::GenBuffers(1, &mut vbo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(gl::ARRAY_BUFFER,
-                (mesh.vertex_data.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
-                mem::transmute(&mesh.vertex_data[0]),
-                gl::STATIC_DRAW);
-
-            // Element buffer
-            gl::GenBuffers(1, &mut ebo);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
-                (mesh.element_data.len() * mem::size_of::<GLuint>()) as GLsizeiptr,
-                mem::transmute(&mesh.element_data[0]),
-                gl::STATIC_DRAW);
-        }
+        let vertex_layout = GLVertexLayout::new(&mesh.layout, format);
+        let index_type = index_type(mesh);
+        let vertex_bytes = vertex_layout.pack(&mesh.layout, &mesh.vertex_data);
+        let index_bytes = pack_indices(&mesh.element_data, index_type);
+        // Create a Vertex Buffer Object and copy the vertex data to it
+        let vbo = backend.create_buffer(gl::ARRAY_BUFFER, &vertex_bytes, usage);
+        // Element buffer
+        let ebo = backend.create_buffer(gl::ELEMENT_ARRAY_BUFFER, &index_bytes, usage);
         println!("Loading GL mesh into memory done.");
         return GLMesh {
+            backend: backend.clone(),
             layout: mesh.layout.clone(),
+            vertex_layout: vertex_layout,
             vbo: vbo,
             ebo: ebo,
-            nindices: mesh.element_data.len() as GLint
+            mode: gl_mode(topology),
+            nindices: Cell::new(mesh.element_data.len() as GLint),
+            index_type: Cell::new(index_type),
+            size_bytes: Cell::new(vertex_bytes.len() + index_bytes.len()),
+            last_drawn: Cell::new(0),
+            usage: usage,
+            vertex_capacity: Cell::new(vertex_bytes.len()),
+            index_capacity: Cell::new(index_bytes.len())
         };
     }
+    pub fn is_dynamic(&self) -> bool {
+        self.usage == gl::DYNAMIC_DRAW
+    }
+    /// Keeps the buffers, so that vertex arrays using them stay valid. Returns false if the mesh is
+    /// not dynamic or the layout differs.
+    pub fn update(&self, mesh: &Mesh) -> bool {
+        if !self.is_dynamic() || !same_layout(&self.layout, &mesh.layout) {
+            return false;
+        }
+        let index_type = index_type(mesh);
+        // Binding the element buffer would change the element binding of the bound vertex array
+        self.backend.bind_vertex_array(0);
+        self.upload(gl::ARRAY_BUFFER, self.vbo, &self.vertex_layout.pack(&mesh.layout, &mesh.vertex_data), &self.vertex_capacity);
+        self.upload(gl::ELEMENT_ARRAY_BUFFER, self.ebo, &pack_indices(&mesh.element_data, index_type), &self.index_capacity);
+        self.nindices.set(mesh.element_data.len() as GLint);
+        self.index_type.set(index_type);
+        self.size_bytes.set(self.vertex_capacity.get() + self.index_capacity.get());
+        true
+    }
+    fn upload(&self, target: GLenum, buffer: GLuint, data: &[u8], capacity: &Cell<usize>) {
+        self.backend.bind_buffer(target, buffer);
+        if data.len() > capacity.get() {
+            self.backend.buffer_data(target, data, self.usage);
+            capacity.set(data.len());
+        } else {
+            self.backend.buffer_sub_data(target, 0, data);
+        }
+    }
+}
 
+fn gl_mode(topology: MeshTopology) -> GLenum {
+    match topology {
+        MeshTopology::Triangles => gl::TRIANGLES,
+        MeshTopology::TriangleStrip => gl::TRIANGLE_STRIP,
+        MeshTopology::Lines => gl::LINES,
+        MeshTopology::LineStrip => gl::LINE_STRIP,
+        MeshTopology::Points => gl::POINTS
+    }
+}
+
+fn same_layout(a: &Layout, b: &Layout) -> bool {
+    a.stride == b.stride && a.attributes.len() == b.attributes.len() &&
+        a.attributes.iter().zip(b.attributes.iter()).all(|(a, b)| a.name == b.name && a.size == b.size && a.offset == b.offset)
+}
+
+impl GPUResource for GLMesh {
+    fn size_bytes(&self) -> usize {
+        self.size_bytes.get()
+    }
+    fn last_drawn(&self) -> u64 {
+        self.last_drawn.get()
+    }
+}
+impl Drop for GLMesh {
+    fn drop(&mut self) {
+        self.backend.delete_buffer(self.vbo);
+        self.backend.delete_buffer(self.ebo);
+    }
 }
 
 #[derive(Debug)]
 pub struct GLVertexArray {
+    backend: Rc<RenderBackend>,
     pub mesh: Rc<GLMesh>,
-    pub vao: GLuint
+    pub vao: GLuint,
+    /// Shader inputs left unbound because the mesh can not provide them.
+    pub diagnostics: Vec<String>
 }
 
 impl GLVertexArray {
-    pub fn new(shader_program: &Rc<GLShaderProgram>, mesh: &Rc<GLMesh>) -> GLVertexArray {
+    pub fn new(backend: &Rc<RenderBackend>, shader_program: &Rc<GLShaderProgram>, mesh: &Rc<GLMesh>) -> GLVertexArray {
+        let diagnostics = check_attributes(shader_program, mesh);
+        for diagnostic in &diagnostics {
+            println!("Vertex array: {}", diagnostic);
+        }
         println!("Loading GL vertex array into memory");
-        let mut vao = 0;
-
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::BindVertexArray(vao);
+        let vao = backend.create_vertex_array();
+        backend.bind_vertex_array(vao);
 
-            gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
+        backend.bind_buffer(gl::ARRAY_BUFFER, mesh.vbo);
 
-            // Specify the layout of the vertex data
-            for attr in &mesh.layout.attributes {
-                let gl_attr = gl::GetAttribLocation(shader_program.program, CString::new(attr.name.clone()).unwrap().as_ptr()) as GLuint;
-                gl::EnableVertexAttribArray(gl_attr);
-                let stride = (mesh.layout.stride * mem::size_of::<GLfloat>()) as GLint;
-                let offset = (attr.offset * mem::size_of::<GLfloat>()) as *const GLvoid;
-                gl::VertexAttribPointer(gl_attr, attr.size as GLint, gl::FLOAT, gl::FALSE as GLboolean, stride, offset);
+        // Specify the layout of the vertex data
+        let stride = mesh.vertex_layout.stride as GLint;
+        for attr in &mesh.vertex_layout.attributes {
+            let gl_attr = match shader_program.attribute(&attr.name) {
+                Some(input) if input.location >= 0 && attr.integer() == is_integer_type(input.ty) => input.location as GLuint,
+                _ => continue
+            };
+            backend.enable_vertex_attrib_array(gl_attr);
+            if attr.integer() {
+                backend.vertex_attrib_i_pointer(gl_attr, attr.size as GLint, attr.gl_type(), stride, attr.offset);
+            } else {
+                backend.vertex_attrib_pointer(gl_attr, attr.size as GLint, attr.gl_type(), attr.normalized(), stride, attr.offset);
             }
         }
         println!("Loading GL vertex array into memory done");
         GLVertexArray {
+            backend: backend.clone(),
             mesh: mesh.clone(),
-            vao: vao
+            vao: vao,
+            diagnostics: diagnostics
         }
     }
 }
+
+fn is_integer_type(ty: GLenum) -> bool {
+    match ty {
+        gl::INT | gl::INT_VEC2 | gl::INT_VEC3 | gl::INT_VEC4 |
+        gl::UNSIGNED_INT | gl::UNSIGNED_INT_VEC2 | gl::UNSIGNED_INT_VEC3 | gl::UNSIGNED_INT_VEC4 => true,
+        _ => false
+    }
+}
+
+fn check_attributes(shader_program: &GLShaderProgram, mesh: &GLMesh) -> Vec<String> {
+    let layout: Vec<&str> = mesh.vertex_layout.attributes.iter().map(|attr| attr.name.as_str()).collect();
+    let mut diagnostics = vec![];
+    // Built in inputs such as gl_VertexID are reported as active attributes too
+    for input in shader_program.attributes.iter().filter(|input| !input.name.starts_with("gl_")) {
+        match mesh.vertex_layout.attributes.iter().find(|attr| attr.name == input.name) {
+            Some(attr) if attr.integer() && !is_integer_type(input.ty) =>
+                diagnostics.push(format!("{} is an integer attribute in the mesh, but not in the shader, so it is left unbound", attr.name)),
+            Some(attr) if !attr.integer() && is_integer_type(input.ty) =>
+                diagnostics.push(format!("{} is an integer input of the shader, but not in the mesh, so it is left unbound", attr.name)),
+            Some(_) => {},
+            None => diagnostics.push(format!("the shader input {} is missing from the mesh layout [{}]", input.name, layout.join(", ")))
+        }
+    }
+    diagnostics
+}
 impl Drop for GLVertexArray {
     fn drop(&mut self) {
-        println!("TODO: clean up GL stuff");
+        self.backend.delete_vertex_array(self.vao);
+    }
+}
+
+const TEXTURE_MAX_ANISOTROPY_EXT: GLenum = 0x84FE;
+
+fn gl_filter(filter: TextureFilter) -> GLenum {
+    match filter {
+        TextureFilter::Nearest => gl::NEAREST,
+        TextureFilter::Linear => gl::LINEAR
+    }
+}
+
+fn gl_min_filter(filter: TextureFilter, mipmaps: bool) -> GLenum {
+    match (filter, mipmaps) {
+        (filter, false) => gl_filter(filter),
+        (TextureFilter::Nearest, true) => gl::NEAREST_MIPMAP_NEAREST,
+        (TextureFilter::Linear, true) => gl::LINEAR_MIPMAP_LINEAR
+    }
+}
+
+fn gl_wrap(wrap: TextureWrap) -> GLenum {
+    match wrap {
+        TextureWrap::Repeat => gl::REPEAT,
+        TextureWrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+        TextureWrap::ClampToEdge => gl::CLAMP_TO_EDGE
+    }
+}
+
+fn apply_sampler(backend: &RenderBackend, target: GLenum, sampler: &TextureSampler) {
+    if sampler.mipmaps {
+        backend.generate_mipmap(target);
+    }
+    backend.tex_parameter(target, gl::TEXTURE_MIN_FILTER, gl_min_filter(sampler.min_filter, sampler.mipmaps) as GLint);
+    backend.tex_parameter(target, gl::TEXTURE_MAG_FILTER, gl_filter(sampler.mag_filter) as GLint);
+    backend.tex_parameter(target, gl::TEXTURE_WRAP_S, gl_wrap(sampler.wrap_s) as GLint);
+    backend.tex_parameter(target, gl::TEXTURE_WRAP_T, gl_wrap(sampler.wrap_t) as GLint);
+    if sampler.anisotropy > 1.0 {
+        backend.tex_parameter_f(target, TEXTURE_MAX_ANISOTROPY_EXT, sampler.anisotropy);
     }
 }
 
+fn float_texture_format(channels: u32, half: bool) -> Result<(GLenum, GLenum), ResourceErr> {
+    Ok(match (channels, half) {
+        (1, false) => (gl::R32F, gl::RED),
+        (2, false) => (gl::RG32F, gl::RG),
+        (3, false) => (gl::RGB32F, gl::RGB),
+        (4, false) => (gl::RGBA32F, gl::RGBA),
+        (1, true) => (gl::R16F, gl::RED),
+        (2, true) => (gl::RG16F, gl::RG),
+        (3, true) => (gl::RGB16F, gl::RGB),
+        (4, true) => (gl::RGBA16F, gl::RGBA),
+        _ => return Err(ResourceErr::Texture(format!("Float textures have 1 to 4 channels, not {}", channels)))
+    })
+}
+
 #[derive(Debug)]
 pub struct GLTexture {
-    pub texture: GLuint
+    backend: Rc<RenderBackend>,
+    pub texture: GLuint,
+    pub target: GLenum,
+    mipmaps: bool,
+    pub size_bytes: usize,
+    pub last_drawn: Cell<u64>
 }
 
 
 impl GLTexture {
-    pub fn new(image: &Texture) -> GLTexture {
+    pub fn new(backend: &Rc<RenderBackend>, image: &Texture, sampler: &TextureSampler) -> GLTexture {
+        match GLTexture::try_new(backend, image, sampler) {
+            Ok(texture) => texture,
+            Err(err) => panic!("{}", err)
+        }
+    }
+    pub fn try_new(backend: &Rc<RenderBackend>, image: &Texture, sampler: &TextureSampler) -> Result<GLTexture, ResourceErr> {
+        let float_format = match image {
+            &Texture::Floats { width, height, channels, half, ref data } => {
+                let format = try!(float_texture_format(channels, half));
+                let expected = (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(channels as usize));
+                if expected != Some(data.len()) {
+                    return Err(ResourceErr::Texture(format!("A {}x{} texture with {} channels can not have {} values", width, height, channels, data.len())));
+                }
+                Some(format)
+            },
+            _ => None
+        };
         println!("Loading GL texture into memory");
-        let mut tex = 0;
-        unsafe {
-            gl::GenTextures(1, &mut tex);
-            gl::BindTexture(gl::TEXTURE_2D, tex);
-            match image {
-                &Texture::Image(ref image) => {
-                    gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as GLint, image.width() as GLint, image.height() as GLint, 0,
-                        gl::RGBA, gl::UNSIGNED_BYTE, mem::transmute(&(&**image as &[u8])[0]));
-                },
-                &Texture::Floats { ref width, ref height, ref data } => {
-                    gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RED as GLint, *width as GLint, *height as GLint, 0,
-                        gl::RED, gl::FLOAT, mem::transmute(&data[0]));
+        let tex = backend.create_texture();
+        let target = match image {
+            &Texture::Cubemap(_) => gl::TEXTURE_CUBE_MAP,
+            _ => gl::TEXTURE_2D
+        };
+        backend.bind_texture(target, tex);
+        let size_bytes = match image {
+            &Texture::Image(ref image) => {
+                backend.tex_image_2d(gl::TEXTURE_2D, gl::RGBA as GLint, image.width(), image.height(),
+                    gl::RGBA, gl::UNSIGNED_BYTE, &**image);
+                (image.width() * image.height() * 4) as usize
+            },
+            &Texture::Floats { width, height, half, ref data, .. } => {
+                let (internal_format, format) = float_format.unwrap();
+                backend.tex_image_2d(gl::TEXTURE_2D, internal_format as GLint, width, height,
+                    format, gl::FLOAT, as_bytes(data));
+                data.len() * if half { 2 } else { mem::size_of::<f32>() }
+            },
+            &Texture::Cubemap(ref faces) => {
+                let mut size_bytes = 0;
+                for (i, face) in faces.iter().enumerate() {
+                    backend.tex_image_2d(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum, gl::RGBA as GLint, face.width(), face.height(),
+                        gl::RGBA, gl::UNSIGNED_BYTE, &**face);
+                    size_bytes += (face.width() * face.height() * 4) as usize;
                 }
+                size_bytes
             }
+        };
+        match image {
+            // Repeating makes no sense for cubemaps, and shows up as seams between the faces
+            &Texture::Cubemap(_) => {
+                apply_sampler(&**backend, target, &TextureSampler {
+                    wrap_s: TextureWrap::ClampToEdge,
+                    wrap_t: TextureWrap::ClampToEdge,
+                    ..sampler.clone()
+                });
+                backend.tex_parameter(target, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+            },
+            _ => apply_sampler(&**backend, target, sampler)
         }
+        // A full mip chain adds a third to the size
+        let size_bytes = if sampler.mipmaps { size_bytes * 4 / 3 } else { size_bytes };
         println!("Loading GL texture into memory done");
-        return GLTexture {
-            texture: tex
-        };
+        Ok(GLTexture {
+            backend: backend.clone(),
+            texture: tex,
+            target: target,
+            mipmaps: sampler.mipmaps,
+            size_bytes: size_bytes,
+            last_drawn: Cell::new(0)
+        })
+    }
+    pub fn update_region(&self, x: u32, y: u32, image: &RgbaImage) {
+        self.backend.bind_texture(self.target, self.texture);
+        self.backend.tex_sub_image_2d(self.target, x, y, image.width(), image.height(),
+            gl::RGBA, gl::UNSIGNED_BYTE, &**image);
+        if self.mipmaps {
+            self.backend.generate_mipmap(self.target);
+        }
+    }
+}
+impl GPUResource for GLTexture {
+    fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+    fn last_drawn(&self) -> u64 {
+        self.last_drawn.get()
+    }
+}
+impl Drop for GLTexture {
+    fn drop(&mut self) {
+        self.backend.delete_texture(self.texture);
     }
 }
 
 #[derive(Debug)]
 pub struct GLShader {
+    backend: Rc<RenderBackend>,
     pub shader: GLuint
 }
 
 impl GLShader {
-    pub fn new(source: &str, ty: GLenum, debug_source_name: &str) -> GLShader {
-        println!("Loading GL shader into memory");
-        let shader;
-
-        unsafe {
-            shader = gl::CreateShader(ty);
-            // Attempt to compile the shader
-            let c_str = CString::new(source.as_bytes()).unwrap();
-            gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
-            gl::CompileShader(shader);
-
-            // Get the compile status
-            let mut status = gl::FALSE as GLint;
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
-
-            // Fail on error
-            if status != (gl::TRUE as GLint) {
-                let mut len = 0;
-                gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-                let mut buf = Vec::with_capacity(len as usize);
-                buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
-                gl::GetShaderInfoLog(shader, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
-                panic!("{}: {}", debug_source_name, str::from_utf8(&buf).ok().expect("ShaderInfoLog not valid utf8"));
-            }
+    pub fn new(backend: &Rc<RenderBackend>, source: &str, ty: GLenum, debug_source_name: &str) -> GLShader {
+        match GLShader::try_new(backend, source, ty, debug_source_name) {
+            Ok(shader) => shader,
+            Err(err) => panic!("{}", err)
         }
+    }
+    pub fn try_new(backend: &Rc<RenderBackend>, source: &str, ty: GLenum, debug_source_name: &str) -> Result<GLShader, String> {
+        println!("Loading GL shader into memory");
+        let shader = match backend.compile_shader(source, ty) {
+            Ok(shader) => shader,
+            Err(log) => return Err(format!("{}: {}", debug_source_name, log))
+        };
         println!("Loading GL shader into memory done");
-        GLShader {
+        Ok(GLShader {
+            backend: backend.clone(),
             shader: shader
-        }
+        })
+    }
+}
+impl Drop for GLShader {
+    fn drop(&mut self) {
+        // Shaders attached to a program are only flagged for deletion until the program is deleted
+        self.backend.delete_shader(self.shader);
     }
 }
 
 #[derive(Debug)]
 pub struct GLShaderProgram {
-    pub program: GLuint
+    backend: Rc<RenderBackend>,
+    pub program: GLuint,
+    pub attributes: Vec<ActiveVariable>,
+    pub uniforms: Vec<ActiveVariable>
 }
 
 impl GLShaderProgram {
-    pub fn new(vs_shader: &GLShader, fs_shader: &GLShader) -> GLShaderProgram {
+    pub fn new(backend: &Rc<RenderBackend>, vs_shader: &GLShader, fs_shader: &GLShader) -> GLShaderProgram {
+        match GLShaderProgram::try_new(backend, vs_shader, fs_shader) {
+            Ok(program) => program,
+            Err(err) => panic!("{}", err)
+        }
+    }
+    pub fn try_new(backend: &Rc<RenderBackend>, vs_shader: &GLShader, fs_shader: &GLShader) -> Result<GLShaderProgram, String> {
         println!("Loading GL shader program into memory");
-        unsafe {
-            let program = gl::CreateProgram();
-            gl::AttachShader(program, vs_shader.shader);
-            gl::AttachShader(program, fs_shader.shader);
-            gl::LinkProgram(program);
-            // Get the link status
-            let mut status = gl::FALSE as GLint;
-            gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
-
-            // Fail on error
-            if status != (gl::TRUE as GLint) {
-                let mut len: GLint = 0;
-                gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
-                let mut buf = Vec::with_capacity(len as usize);
-                buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
-                gl::GetProgramInfoLog(program, len, ptr::null_mut(), buf.as_mut_ptr() as *mut GLchar);
-                panic!("{}", str::from_utf8(&buf).ok().expect("ProgramInfoLog not valid utf8"));
-            }
-            println!("Loading GL shader program into memory done");
-            GLShaderProgram {
-                program: program
-            }
+        let program = try!(backend.link_program(&[vs_shader.shader, fs_shader.shader]));
+        println!("Loading GL shader program into memory done");
+        Ok(GLShaderProgram {
+            backend: backend.clone(),
+            program: program,
+            attributes: backend.active_attributes(program),
+            uniforms: backend.active_uniforms(program)
+        })
+    }
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.iter().find(|uniform| uniform.name == name)
+    }
+    pub fn from_source(backend: &Rc<RenderBackend>, source: &ShaderSource) -> Result<GLShaderProgram, String> {
+        let vs = try!(GLShader::try_new(backend, &source.vertex_src, gl::VERTEX_SHADER, &source.vertex_debug_source_name));
+        let fs = try!(GLShader::try_new(backend, &source.fragment_src, gl::FRAGMENT_SHADER, &source.fragment_debug_source_name));
+        GLShaderProgram::try_new(backend, &vs, &fs)
+    }
+}
+impl Drop for GLShaderProgram {
+    fn drop(&mut self) {
+        self.backend.delete_program(self.program);
+    }
+}
+
+#[derive(Debug)]
+pub struct GLFramebuffer {
+    backend: Rc<RenderBackend>,
+    pub fbo: GLuint,
+    pub color: GLuint,
+    pub depth: GLuint,
+    pub width: u32,
+    pub height: u32
+}
+
+impl GLFramebuffer {
+    pub fn new(backend: &Rc<RenderBackend>, width: u32, height: u32) -> Result<GLFramebuffer, String> {
+        println!("Creating GL framebuffer {}x{}", width, height);
+        let fbo = backend.create_framebuffer();
+        backend.bind_framebuffer(gl::FRAMEBUFFER, fbo);
+        let color = backend.create_renderbuffer(gl::RGBA8, width, height);
+        backend.framebuffer_renderbuffer(gl::COLOR_ATTACHMENT0, color);
+        let depth = backend.create_renderbuffer(gl::DEPTH_COMPONENT24, width, height);
+        backend.framebuffer_renderbuffer(gl::DEPTH_ATTACHMENT, depth);
+        let framebuffer = GLFramebuffer {
+            backend: backend.clone(),
+            fbo: fbo,
+            color: color,
+            depth: depth,
+            width: width,
+            height: height
+        };
+        let status = backend.check_framebuffer_status();
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Framebuffer incomplete: 0x{:x}", status));
         }
+        Ok(framebuffer)
+    }
+}
+impl Drop for GLFramebuffer {
+    fn drop(&mut self) {
+        self.backend.delete_framebuffer(self.fbo);
+        self.backend.delete_renderbuffer(self.color);
+        self.backend.delete_renderbuffer(self.depth);
     }
 }
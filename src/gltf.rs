@@ -0,0 +1,438 @@
+//! A loader for `.gltf` and `.glb` assets. Only meshes and base color textures are read.
+
+extern crate image;
+
+use mesh::*;
+use pon_to_resource::ResourceErr;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use image::RgbaImage;
+use rustc_serialize::base64::FromBase64;
+use rustc_serialize::json::Json;
+use std::cmp;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+const MODE_TRIANGLES: u64 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GltfRef {
+    Name(String),
+    Index(usize)
+}
+
+struct GltfFile {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+    base_path: PathBuf
+}
+
+type Matrix = [f32; 16];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    let mut file = try!(File::open(path).map_err(|err| format!("couldn't open {:?}: {:?}", path, err)));
+    let mut data = vec![];
+    try!(file.read_to_end(&mut data).map_err(|err| format!("couldn't read {:?}: {:?}", path, err)));
+    Ok(data)
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, String> {
+    json.find(key).ok_or(format!("missing field {}", key))
+}
+
+fn field_array<'a>(json: &'a Json, key: &str) -> Result<&'a Vec<Json>, String> {
+    try!(field(json, key)).as_array().ok_or(format!("field {} is not an array", key))
+}
+
+fn field_usize(json: &Json, key: &str) -> Result<usize, String> {
+    try!(field(json, key)).as_u64().map(|x| x as usize).ok_or(format!("field {} is not an integer", key))
+}
+
+fn field_usize_or(json: &Json, key: &str, default: usize) -> Result<usize, String> {
+    match json.find(key) {
+        Some(_) => field_usize(json, key),
+        None => Ok(default)
+    }
+}
+
+fn field_floats(json: &Json, key: &str) -> Result<Option<Vec<f32>>, String> {
+    match json.find(key) {
+        Some(value) => {
+            let array = try!(value.as_array().ok_or(format!("field {} is not an array", key)));
+            let floats: Option<Vec<f32>> = array.iter().map(|x| x.as_f64().map(|x| x as f32)).collect();
+            floats.map(|x| Some(x)).ok_or(format!("field {} is not an array of numbers", key))
+        },
+        None => Ok(None)
+    }
+}
+
+fn load_gltf_file(path: &Path) -> Result<GltfFile, String> {
+    let data = try!(read_file(path));
+    let base_path = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let (json_text, bin) = if path.extension().and_then(|ext| ext.to_str()) == Some("glb") {
+        try!(split_glb(&data))
+    } else {
+        (try!(String::from_utf8(data).map_err(|_| "file is not valid utf-8".to_string())), None)
+    };
+    let json = try!(Json::from_str(&json_text).map_err(|err| format!("invalid json: {:?}", err)));
+    let mut buffers = vec![];
+    if let Some(array) = json.find("buffers").and_then(|b| b.as_array()) {
+        for (i, buffer) in array.iter().enumerate() {
+            let data = match buffer.find("uri").and_then(|uri| uri.as_string()) {
+                Some(uri) => try!(load_uri(&base_path, uri)),
+                None => match (i, &bin) {
+                    (0, &Some(ref bin)) => bin.clone(),
+                    _ => return Err(format!("buffer {} has no uri", i))
+                }
+            };
+            buffers.push(data);
+        }
+    }
+    Ok(GltfFile { json: json, buffers: buffers, base_path: base_path })
+}
+
+fn split_glb(data: &[u8]) -> Result<(String, Option<Vec<u8>>), String> {
+    let mut rdr = data;
+    let magic = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+    let version = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+    let length = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as usize;
+    if magic != GLB_MAGIC || version != 2 {
+        return Err("not a glTF 2.0 binary file".to_string());
+    }
+    if length > data.len() {
+        return Err("file is truncated".to_string());
+    }
+    let mut offset = 12;
+    let mut json = None;
+    let mut bin = None;
+    while offset + 8 <= length {
+        let mut header = &data[offset..];
+        let chunk_length = try!(header.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as usize;
+        let chunk_type = try!(header.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+        let start = offset + 8;
+        if start + chunk_length > length {
+            return Err("chunk is truncated".to_string());
+        }
+        let chunk = &data[start..(start + chunk_length)];
+        match chunk_type {
+            GLB_CHUNK_JSON => json = Some(try!(String::from_utf8(chunk.to_vec()).map_err(|_| "json chunk is not valid utf-8".to_string()))),
+            GLB_CHUNK_BIN => bin = Some(chunk.to_vec()),
+            _ => {}
+        }
+        offset = start + chunk_length;
+    }
+    match json {
+        Some(json) => Ok((json, bin)),
+        None => Err("missing json chunk".to_string())
+    }
+}
+
+fn load_uri(base_path: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if uri.starts_with("data:") {
+        match uri.find("base64,") {
+            Some(i) => uri[(i + 7)..].from_base64().map_err(|err| format!("invalid base64 data: {:?}", err)),
+            None => Err("only base64 data uris are supported".to_string())
+        }
+    } else {
+        read_file(&base_path.join(uri))
+    }
+}
+
+fn component_size(component_type: u64) -> Result<usize, String> {
+    match component_type {
+        5120 | 5121 => Ok(1),
+        5122 | 5123 => Ok(2),
+        5125 | 5126 => Ok(4),
+        _ => Err(format!("unknown component type {}", component_type))
+    }
+}
+
+fn type_components(type_name: &str) -> Result<usize, String> {
+    match type_name {
+        "SCALAR" => Ok(1),
+        "VEC2" => Ok(2),
+        "VEC3" => Ok(3),
+        "VEC4" => Ok(4),
+        "MAT4" => Ok(16),
+        _ => Err(format!("unsupported accessor type {}", type_name))
+    }
+}
+
+fn mat_mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut c = [0.0; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            let mut sum = 0.0;
+            for k in 0..4 {
+                sum += a[k * 4 + row] * b[col * 4 + k];
+            }
+            c[col * 4 + row] = sum;
+        }
+    }
+    c
+}
+
+fn transform_point(m: &Matrix, p: &[f32]) -> [f32; 3] {
+    [m[0] * p[0] + m[4] * p[1] + m[8] * p[2] + m[12],
+     m[1] * p[0] + m[5] * p[1] + m[9] * p[2] + m[13],
+     m[2] * p[0] + m[6] * p[1] + m[10] * p[2] + m[14]]
+}
+
+/// Transforms a normal by the cofactor matrix of the upper 3x3 of `m`, which is the inverse
+/// transpose up to scale, so non-uniform scaling is handled too.
+fn transform_normal(m: &Matrix, n: &[f32]) -> [f32; 3] {
+    let a = |row: usize, col: usize| m[col * 4 + row];
+    let cofactor = |row: usize, col: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((col + 1) % 3, (col + 2) % 3);
+        a(r0, c0) * a(r1, c1) - a(r0, c1) * a(r1, c0)
+    };
+    let det = a(0, 0) * cofactor(0, 0) + a(0, 1) * cofactor(0, 1) + a(0, 2) * cofactor(0, 2);
+    let sign = if det < 0.0 { -1.0 } else { 1.0 };
+    let mut r = [0.0; 3];
+    for row in 0..3 {
+        r[row] = sign * (cofactor(row, 0) * n[0] + cofactor(row, 1) * n[1] + cofactor(row, 2) * n[2]);
+    }
+    let len = (r[0] * r[0] + r[1] * r[1] + r[2] * r[2]).sqrt();
+    if len > 0.0 { [r[0] / len, r[1] / len, r[2] / len] } else { r }
+}
+
+impl GltfFile {
+    fn array(&self, key: &str) -> Result<&Vec<Json>, String> {
+        field_array(&self.json, key)
+    }
+    fn element(&self, key: &str, index: usize) -> Result<&Json, String> {
+        try!(self.array(key)).get(index).ok_or(format!("{} {} does not exist", key, index))
+    }
+    fn find_index(&self, key: &str, reference: &GltfRef) -> Result<usize, String> {
+        match reference {
+            &GltfRef::Index(index) => {
+                try!(self.element(key, index));
+                Ok(index)
+            },
+            &GltfRef::Name(ref name) => try!(self.array(key)).iter()
+                .position(|x| x.find("name").and_then(|n| n.as_string()) == Some(name))
+                .ok_or(format!("no {} named {}", key, name))
+        }
+    }
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], usize), String> {
+        let view = try!(self.element("bufferViews", index));
+        let buffer = try!(self.buffers.get(try!(field_usize(view, "buffer"))).ok_or("buffer does not exist".to_string()));
+        let offset = try!(field_usize_or(view, "byteOffset", 0));
+        let length = try!(field_usize(view, "byteLength"));
+        let stride = try!(field_usize_or(view, "byteStride", 0));
+        if offset + length > buffer.len() {
+            return Err(format!("buffer view {} is out of bounds", index));
+        }
+        Ok((&buffer[offset..(offset + length)], stride))
+    }
+    fn accessor_floats(&self, index: usize) -> Result<(Vec<f32>, usize), String> {
+        let accessor = try!(self.element("accessors", index));
+        let component_type = try!(try!(field(accessor, "componentType")).as_u64().ok_or("invalid componentType".to_string()));
+        let components = try!(type_components(try!(try!(field(accessor, "type")).as_string().ok_or("invalid type".to_string()))));
+        let count = try!(field_usize(accessor, "count"));
+        let size = try!(component_size(component_type));
+        let (data, stride) = try!(self.buffer_view(try!(field_usize(accessor, "bufferView"))));
+        let offset = try!(field_usize_or(accessor, "byteOffset", 0));
+        let stride = if stride == 0 { components * size } else { stride };
+        if count > 0 && offset + (count - 1) * stride + components * size > data.len() {
+            return Err(format!("accessor {} is out of bounds", index));
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let mut rdr = &data[(offset + i * stride + c * size)..];
+                let value = match component_type {
+                    5126 => rdr.read_f32::<LittleEndian>().ok(),
+                    5121 => rdr.read_u8().ok().map(|x| x as f32 / 255.0),
+                    5123 => rdr.read_u16::<LittleEndian>().ok().map(|x| x as f32 / 65535.0),
+                    5125 => rdr.read_u32::<LittleEndian>().ok().map(|x| x as f32),
+                    _ => return Err(format!("accessor {} can not be read as floats", index))
+                };
+                values.push(try!(value.ok_or(format!("accessor {} is out of bounds", index))));
+            }
+        }
+        Ok((values, components))
+    }
+    fn accessor_indices(&self, index: usize) -> Result<Vec<u32>, String> {
+        let accessor = try!(self.element("accessors", index));
+        let component_type = try!(try!(field(accessor, "componentType")).as_u64().ok_or("invalid componentType".to_string()));
+        let count = try!(field_usize(accessor, "count"));
+        let size = try!(component_size(component_type));
+        let (data, stride) = try!(self.buffer_view(try!(field_usize(accessor, "bufferView"))));
+        let offset = try!(field_usize_or(accessor, "byteOffset", 0));
+        let stride = if stride == 0 { size } else { stride };
+        let mut indices = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = offset + i * stride;
+            if start + size > data.len() {
+                return Err(format!("accessor {} is out of bounds", index));
+            }
+            let mut rdr = &data[start..];
+            indices.push(match component_type {
+                5121 => rdr.read_u8().unwrap() as u32,
+                5123 => rdr.read_u16::<LittleEndian>().unwrap() as u32,
+                5125 => rdr.read_u32::<LittleEndian>().unwrap(),
+                _ => return Err(format!("accessor {} is not an index accessor", index))
+            });
+        }
+        Ok(indices)
+    }
+    fn primitive_mesh(&self, mesh: usize, primitive: usize, transform: &Matrix) -> Result<Mesh, String> {
+        let primitives = try!(field_array(try!(self.element("meshes", mesh)), "primitives"));
+        let primitive = try!(primitives.get(primitive).ok_or(format!("mesh {} has no primitive {}", mesh, primitive)));
+        if try!(field_usize_or(primitive, "mode", MODE_TRIANGLES as usize)) as u64 != MODE_TRIANGLES {
+            return Err("only triangle primitives are supported".to_string());
+        }
+        let attributes = try!(field(primitive, "attributes"));
+        let (positions, _) = try!(self.accessor_floats(try!(field_usize(attributes, "POSITION"))));
+        let n_vertices = positions.len() / 3;
+        let texcoords = match attributes.find("TEXCOORD_0") {
+            Some(_) => Some(try!(self.accessor_floats(try!(field_usize(attributes, "TEXCOORD_0")))).0),
+            None => None
+        };
+        let normals = match attributes.find("NORMAL") {
+            Some(_) => Some(try!(self.accessor_floats(try!(field_usize(attributes, "NORMAL")))).0),
+            None => None
+        };
+        let element_data = match primitive.find("indices") {
+            Some(_) => try!(self.accessor_indices(try!(field_usize(primitive, "indices")))),
+            None => (0..n_vertices as u32).collect()
+        };
+        if element_data.iter().any(|&i| i as usize >= n_vertices) {
+            return Err("index out of range".to_string());
+        }
+
+        let layout = Layout::position_texcoord_normal();
+        let mut vertex_data = vec![0.0; n_vertices * layout.stride];
+        for i in 0..n_vertices {
+            let position = transform_point(transform, &positions[(i * 3)..(i * 3 + 3)]);
+            let texcoord = match texcoords {
+                Some(ref t) if t.len() >= i * 2 + 2 => [t[i * 2], t[i * 2 + 1]],
+                _ => [0.0, 0.0]
+            };
+            let normal = match normals {
+                Some(ref n) if n.len() >= i * 3 + 3 => transform_normal(transform, &n[(i * 3)..(i * 3 + 3)]),
+                _ => [0.0, 0.0, 0.0]
+            };
+            for attribute in &layout.attributes {
+                let values: &[f32] = match attribute.name.as_str() {
+                    "position" => &position,
+                    "texcoord" => &texcoord,
+                    "normal" => &normal,
+                    _ => continue
+                };
+                let start = i * layout.stride + attribute.offset;
+                for j in 0..cmp::min(attribute.size, values.len()) {
+                    vertex_data[start + j] = values[j];
+                }
+            }
+        }
+        Ok(Mesh {
+            layout: layout,
+            vertex_data: vertex_data,
+            element_data: element_data
+        })
+    }
+    fn local_transform(&self, node: usize) -> Result<Matrix, String> {
+        let node = try!(self.element("nodes", node));
+        if let Some(m) = try!(field_floats(node, "matrix")) {
+            if m.len() != 16 {
+                return Err("node matrix must have 16 elements".to_string());
+            }
+            let mut matrix = [0.0; 16];
+            for i in 0..16 {
+                matrix[i] = m[i];
+            }
+            return Ok(matrix);
+        }
+        let t = try!(field_floats(node, "translation")).unwrap_or(vec![0.0, 0.0, 0.0]);
+        let r = try!(field_floats(node, "rotation")).unwrap_or(vec![0.0, 0.0, 0.0, 1.0]);
+        let s = try!(field_floats(node, "scale")).unwrap_or(vec![1.0, 1.0, 1.0]);
+        if t.len() != 3 || r.len() != 4 || s.len() != 3 {
+            return Err("invalid node transform".to_string());
+        }
+        let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+        let rotation = [
+            1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w), 0.0,
+            2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w), 0.0,
+            2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y), 0.0,
+            0.0, 0.0, 0.0, 1.0];
+        let scale = [s[0], 0.0, 0.0, 0.0, 0.0, s[1], 0.0, 0.0, 0.0, 0.0, s[2], 0.0, 0.0, 0.0, 0.0, 1.0];
+        let translation = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, t[0], t[1], t[2], 1.0];
+        Ok(mat_mul(&translation, &mat_mul(&rotation, &scale)))
+    }
+    fn parent(&self, node: usize) -> Option<usize> {
+        let nodes = match self.array("nodes") {
+            Ok(nodes) => nodes,
+            Err(_) => return None
+        };
+        nodes.iter().position(|n| match n.find("children").and_then(|c| c.as_array()) {
+            Some(children) => children.iter().any(|c| c.as_u64() == Some(node as u64)),
+            None => false
+        })
+    }
+    fn world_transform(&self, node: usize) -> Result<Matrix, String> {
+        let mut transform = try!(self.local_transform(node));
+        let mut current = node;
+        let mut depth = 0;
+        while let Some(parent) = self.parent(current) {
+            depth += 1;
+            if depth > 1000 {
+                return Err("node hierarchy contains a cycle".to_string());
+            }
+            transform = mat_mul(&try!(self.local_transform(parent)), &transform);
+            current = parent;
+        }
+        Ok(transform)
+    }
+    fn base_color_texture(&self, material: usize) -> Result<RgbaImage, String> {
+        let material_json = try!(self.element("materials", material));
+        let texture = try!(material_json.find("pbrMetallicRoughness")
+            .and_then(|pbr| pbr.find("baseColorTexture"))
+            .ok_or(format!("material {} has no base color texture", material)));
+        let texture = try!(self.element("textures", try!(field_usize(texture, "index"))));
+        let image = try!(self.element("images", try!(field_usize(texture, "source"))));
+        let data = match image.find("uri").and_then(|uri| uri.as_string()) {
+            Some(uri) => try!(load_uri(&self.base_path, uri)),
+            None => try!(self.buffer_view(try!(field_usize(image, "bufferView")))).0.to_vec()
+        };
+        image::load_from_memory(&data).map(|img| img.to_rgba()).map_err(|err| format!("failed to decode image: {:?}", err))
+    }
+}
+
+fn gltf_err(path: &Path, message: String) -> ResourceErr {
+    ResourceErr::Io { path: path.to_path_buf(), message: message }
+}
+
+pub fn load_mesh(path: &Path, mesh: &GltfRef, primitive: usize) -> Result<Mesh, ResourceErr> {
+    println!("Loading gltf mesh {:?} from {:?}", mesh, path);
+    let file = try!(load_gltf_file(path).map_err(|err| gltf_err(path, err)));
+    let mesh = try!(file.find_index("meshes", mesh).map_err(|err| gltf_err(path, err)));
+    file.primitive_mesh(mesh, primitive, &IDENTITY).map_err(|err| gltf_err(path, err))
+}
+
+/// The node's world transform is baked into the vertices.
+pub fn load_node_mesh(path: &Path, node: &GltfRef, primitive: usize) -> Result<Mesh, ResourceErr> {
+    println!("Loading gltf node {:?} from {:?}", node, path);
+    let file = try!(load_gltf_file(path).map_err(|err| gltf_err(path, err)));
+    let result = file.find_index("nodes", node).and_then(|node| {
+        let mesh = try!(field_usize(try!(file.element("nodes", node)), "mesh"));
+        let transform = try!(file.world_transform(node));
+        file.primitive_mesh(mesh, primitive, &transform)
+    });
+    result.map_err(|err| gltf_err(path, err))
+}
+
+pub fn load_base_color_texture(path: &Path, material: &GltfRef) -> Result<RgbaImage, ResourceErr> {
+    println!("Loading gltf material {:?} from {:?}", material, path);
+    let file = try!(load_gltf_file(path).map_err(|err| gltf_err(path, err)));
+    let result = file.find_index("materials", material).and_then(|material| file.base_color_texture(material));
+    result.map_err(|err| gltf_err(path, err))
+}
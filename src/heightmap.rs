@@ -0,0 +1,144 @@
+//! Terrain meshes displaced by a heightmap. Chunks share their edge vertices and normals, so they
+//! fit together without seams.
+
+use mesh::*;
+use pon_to_resource::{Texture, ResourceErr, texture_from_file};
+
+use std::cmp;
+use std::path::Path;
+
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    pub heights: Vec<f32>
+}
+
+impl Heightmap {
+    pub fn from_texture(texture: Texture) -> Result<Heightmap, String> {
+        let heightmap = match texture {
+            Texture::Floats { width, height, channels, data, .. } => Heightmap {
+                width: width,
+                height: height,
+                heights: data.chunks(channels as usize).map(|texel| texel[0]).collect()
+            },
+            Texture::Image(image) => Heightmap {
+                width: image.width(),
+                height: image.height(),
+                heights: image.pixels().map(|p| (p.data[0] as f32 + p.data[1] as f32 + p.data[2] as f32) / (3.0 * 255.0)).collect()
+            },
+            Texture::Cubemap(_) => return Err("A cubemap can not be used as a heightmap".to_string())
+        };
+        if heightmap.width < 2 || heightmap.height < 2 {
+            return Err(format!("A heightmap needs at least 2x2 samples, got {}x{}", heightmap.width, heightmap.height));
+        }
+        Ok(heightmap)
+    }
+    fn get(&self, x: i64, y: i64) -> f32 {
+        let x = cmp::min(cmp::max(x, 0), self.width as i64 - 1) as usize;
+        let y = cmp::min(cmp::max(y, 0), self.height as i64 - 1) as usize;
+        self.heights[y * self.width as usize + x]
+    }
+    pub fn chunk_count(&self, chunk_size: u32) -> Option<(u32, u32)> {
+        let count = |quads: u32| quads.checked_div(chunk_size).map(|n| if quads % chunk_size == 0 { n } else { n + 1 });
+        match (count(self.width - 1), count(self.height - 1)) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None
+        }
+    }
+}
+
+pub fn load_heightmap(path: &Path) -> Result<Heightmap, ResourceErr> {
+    let texture = try!(texture_from_file(path));
+    Heightmap::from_texture(texture).map_err(|err| ResourceErr::Io { path: path.to_path_buf(), message: err })
+}
+
+/// `size` quads wide. Chunks at the far edges can be smaller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainChunk {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32
+}
+
+#[derive(Clone)]
+pub struct TerrainOptions {
+    pub layout: Layout,
+    pub horizontal_scale: f32,
+    pub vertical_scale: f32,
+    pub chunk: Option<TerrainChunk>
+}
+
+/// Texture coordinates span the whole terrain, also in chunks.
+pub fn heightmap_to_mesh(heightmap: &Heightmap, options: &TerrainOptions) -> Result<Mesh, String> {
+    let (x0, y0, x1, y1) = match options.chunk {
+        Some(chunk) => {
+            let (chunks_x, chunks_y) = match heightmap.chunk_count(chunk.size) {
+                Some(count) => count,
+                None => return Err("The chunk size has to be at least 1".to_string())
+            };
+            if chunk.x >= chunks_x || chunk.y >= chunks_y {
+                return Err(format!("Chunk {}, {} is outside of the {}x{} chunks of the heightmap", chunk.x, chunk.y, chunks_x, chunks_y));
+            }
+            // The first and last sample of the chunk along one axis, clamped to the heightmap
+            let range = |index: u32, samples: u32| match index.checked_mul(chunk.size) {
+                Some(start) => Some((start, start.checked_add(chunk.size).map_or(samples - 1, |end| cmp::min(end, samples - 1)))),
+                None => None
+            };
+            match (range(chunk.x, heightmap.width), range(chunk.y, heightmap.height)) {
+                (Some((x0, x1)), Some((y0, y1))) => (x0, y0, x1, y1),
+                _ => return Err(format!("Chunk {}, {} is outside of the heightmap", chunk.x, chunk.y))
+            }
+        },
+        None => (0, 0, heightmap.width - 1, heightmap.height - 1)
+    };
+    let h = options.horizontal_scale;
+    let v = options.vertical_scale;
+    let (offset_x, offset_z) = ((heightmap.width - 1) as f32 * h / 2.0, (heightmap.height - 1) as f32 * h / 2.0);
+    let layout = options.layout.clone();
+    let columns = (x1 - x0 + 1) as usize;
+    let rows = (y1 - y0 + 1) as usize;
+    let mut vertex_data = vec![0.0; columns * rows * layout.stride];
+    for y in y0..(y1 + 1) {
+        for x in x0..(x1 + 1) {
+            let (xi, yi) = (x as i64, y as i64);
+            let position = [x as f32 * h - offset_x, heightmap.get(xi, yi) * v, y as f32 * h - offset_z];
+            let texcoord = [x as f32 / (heightmap.width - 1) as f32, y as f32 / (heightmap.height - 1) as f32];
+            // Central differences, one sided at the edges of the heightmap
+            let (left, right) = (cmp::max(xi - 1, 0), cmp::min(xi + 1, heightmap.width as i64 - 1));
+            let (back, front) = (cmp::max(yi - 1, 0), cmp::min(yi + 1, heightmap.height as i64 - 1));
+            let dx = (heightmap.get(right, yi) - heightmap.get(left, yi)) * v / ((right - left) as f32 * h);
+            let dz = (heightmap.get(xi, front) - heightmap.get(xi, back)) * v / ((front - back) as f32 * h);
+            let length = (dx * dx + 1.0 + dz * dz).sqrt();
+            let normal = [-dx / length, 1.0 / length, -dz / length];
+            let i = (y - y0) as usize * columns + (x - x0) as usize;
+            for attribute in &layout.attributes {
+                let values: &[f32] = match attribute.name.as_str() {
+                    "position" => &position,
+                    "texcoord" => &texcoord,
+                    "normal" => &normal,
+                    _ => continue
+                };
+                let start = i * layout.stride + attribute.offset;
+                for j in 0..cmp::min(attribute.size, values.len()) {
+                    vertex_data[start + j] = values[j];
+                }
+            }
+        }
+    }
+    let mut element_data = Vec::with_capacity((columns - 1) * (rows - 1) * 6);
+    for row in 0..(rows - 1) {
+        for column in 0..(columns - 1) {
+            let a = (row * columns + column) as u32;
+            let b = a + 1;
+            let d = a + columns as u32;
+            let c = d + 1;
+            // Counter clockwise seen from above
+            element_data.extend([a, c, b, a, d, c].iter().cloned());
+        }
+    }
+    Ok(Mesh {
+        layout: layout,
+        vertex_data: vertex_data,
+        element_data: element_data
+    })
+}
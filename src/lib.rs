@@ -1,4 +1,4 @@
-#![feature(box_patterns, rc_weak, convert, unboxed_closures, core)]
+#![feature(box_patterns, rc_weak, rc_counts, convert, unboxed_closures, core)]
 
 extern crate gl;
 extern crate libc;
@@ -11,13 +11,25 @@ extern crate pyramid;
 extern crate glutin;
 extern crate mesh;
 extern crate ppromise;
+extern crate rustc_serialize;
 
-mod renderer;
-mod resources;
-mod gl_resources;
+pub mod renderer;
+pub mod resources;
+pub mod gl_resources;
+pub mod backend;
 mod fps_counter;
-mod pon_to_resource;
-mod shader_uniforms;
+pub mod pon_to_resource;
+pub mod shader_uniforms;
+pub mod pmesh;
+pub mod dynamic_texture;
+mod render_target;
+mod file_watcher;
+mod obj_loader;
+mod gltf;
+mod procedural_textures;
+pub mod primitives;
+pub mod mesh_processing;
+pub mod heightmap;
 
 use pyramid::interface::*;
 use pyramid::pon::*;
@@ -32,6 +44,8 @@ use resources::*;
 use fps_counter::*;
 use pon_to_resource::*;
 use shader_uniforms::*;
+use render_target::*;
+use backend::*;
 
 use image::RgbaImage;
 use std::collections::HashMap;
@@ -48,16 +62,26 @@ use ppromise::*;
 
 static SHADER_BASIC_VS: &'static [u8] = include_bytes!("../shaders/basic_vs.glsl");
 static SHADER_BASIC_FS: &'static [u8] = include_bytes!("../shaders/basic_fs.glsl");
+static SHADER_SKYBOX_VS: &'static [u8] = include_bytes!("../shaders/skybox_vs.glsl");
+static SHADER_SKYBOX_FS: &'static [u8] = include_bytes!("../shaders/skybox_fs.glsl");
 
 struct PendingAdd {
     id: EntityId,
-    resources: Promise<RenderNodeResources>,
+    resources: Promise<(RenderNodeResources, Vec<ResourceErr>)>,
     config: RenderNodeConfig
 }
 
+struct RenderableKeys {
+    mesh: Pon,
+    shader: Pon,
+    texture_ids: Vec<String>,
+    textures: Vec<Pon>,
+    dynamic_mesh: bool
+}
+
 pub struct ViewportSubSystem {
     root_path: PathBuf,
-    window: glutin::Window,
+    target: RenderTarget,
     renderer: Renderer,
     resources: Resources,
     pending_add: Vec<PendingAdd>,
@@ -65,53 +89,211 @@ pub struct ViewportSubSystem {
     fps_counter: FpsCounter,
     start_time: Timespec,
     prev_time: Timespec,
-    first_load_timed: bool
+    first_load_timed: bool,
+    pending_screenshot: Option<PathBuf>,
+    collect_resources: bool,
+    entity_resource_keys: HashMap<EntityId, Vec<Pon>>,
+    render_errors: Vec<(EntityId, Option<String>)>,
+    entities_with_errors: HashSet<EntityId>,
+    skybox_shader: Rc<GLShaderProgram>,
+    skybox_vertex_array: Rc<GLVertexArray>,
+    skybox: Option<(EntityId, Pon)>,
+    pending_skybox: Option<Promise<Result<Rc<GLTexture>, ResourceErr>>>,
+    dynamic_mesh_entities: HashSet<EntityId>,
+    pending_mesh_updates: Vec<(EntityId, Promise<Result<Rc<Mesh>, ResourceErr>>)>,
+    readd_entities: Vec<EntityId>
 }
 
 impl ViewportSubSystem {
     pub fn new(root_path: PathBuf) -> ViewportSubSystem {
-        let window = glutin::Window::new().unwrap();
-
-        unsafe { window.make_current() };
-
+        let target = RenderTarget::new_window();
+        ViewportSubSystem::new_with_target(root_path, Rc::new(GLBackend), target)
+    }
+    pub fn new_headless(root_path: PathBuf, width: u32, height: u32) -> Result<ViewportSubSystem, String> {
+        let backend: Rc<RenderBackend> = Rc::new(GLBackend);
+        let target = try!(RenderTarget::new_headless(&backend, width, height));
+        Ok(ViewportSubSystem::new_with_target(root_path, backend, target))
+    }
+    fn new_with_target(root_path: PathBuf, backend: Rc<RenderBackend>, target: RenderTarget) -> ViewportSubSystem {
         unsafe {
-            gl::load_with(|symbol| window.get_proc_address(symbol));
             gl::ClearColor(1.0, 1.0, 0.0, 1.0);
         }
 
+        let skybox_shader = Rc::new(GLShaderProgram::new(&backend,
+            &GLShader::new(&backend, str::from_utf8(SHADER_SKYBOX_VS).unwrap(), gl::VERTEX_SHADER, "bundled skybox"),
+            &GLShader::new(&backend, str::from_utf8(SHADER_SKYBOX_FS).unwrap(), gl::FRAGMENT_SHADER, "bundled skybox")));
+        let skybox_quad = Rc::new(GLMesh::new(&backend, &Mesh {
+            layout: Layout::new(vec![AttributeSpec("position".to_string(), 3)]),
+            vertex_data: vec![-1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0, 1.0, 1.0, -1.0, 1.0, 1.0],
+            element_data: vec![0, 1, 2, 0, 2, 3]
+        }));
+        let skybox_vertex_array = Rc::new(GLVertexArray::new(&backend, &skybox_shader, &skybox_quad));
         let mut viewport = ViewportSubSystem {
             root_path: root_path.clone(),
-            window: window,
-            renderer: Renderer::new(),
-            resources: Resources::new(root_path.clone()),
+            target: target,
+            renderer: Renderer::new(backend.clone()),
+            resources: Resources::new(root_path.clone(), backend.clone()),
             pending_add: vec![],
             default_textures: Pon::from_string("{ diffuse: static_texture { pixels: [255, 0, 0, 255], width: 1, height: 1 } }").unwrap(),
             fps_counter: FpsCounter::new(),
             start_time: time::get_time(),
             prev_time: time::get_time(),
-            first_load_timed: false
+            first_load_timed: false,
+            pending_screenshot: None,
+            collect_resources: false,
+            entity_resource_keys: HashMap::new(),
+            render_errors: vec![],
+            entities_with_errors: HashSet::new(),
+            skybox_shader: skybox_shader,
+            skybox_vertex_array: skybox_vertex_array,
+            skybox: None,
+            pending_skybox: None,
+            dynamic_mesh_entities: HashSet::new(),
+            pending_mesh_updates: vec![],
+            readd_entities: vec![]
         };
 
-        let shader_program = GLShaderProgram::new(
-            &GLShader::new(str::from_utf8(SHADER_BASIC_VS).unwrap(), gl::VERTEX_SHADER, "bundled"),
-            &GLShader::new(str::from_utf8(SHADER_BASIC_FS).unwrap(), gl::FRAGMENT_SHADER, "bundled"));
+        let shader_program = GLShaderProgram::new(&backend,
+            &GLShader::new(&backend, str::from_utf8(SHADER_BASIC_VS).unwrap(), gl::VERTEX_SHADER, "bundled"),
+            &GLShader::new(&backend, str::from_utf8(SHADER_BASIC_FS).unwrap(), gl::FRAGMENT_SHADER, "bundled"));
 
-        viewport.resources.gl_shader_programs.insert(Pon::String("basic".to_string()), Promise::resolved(Rc::new(shader_program)));
+        viewport.resources.add_persistent_shader_program(Pon::String("basic".to_string()), shader_program);
 
         viewport
     }
+    pub fn is_headless(&self) -> bool {
+        self.target.is_headless()
+    }
+    pub fn is_loading(&self) -> bool {
+        self.pending_add.len() > 0 || self.pending_skybox.is_some() || self.pending_mesh_updates.len() > 0
+    }
+    pub fn render_frame(&mut self) {
+        let total_time = time::get_time() - self.start_time;
+
+        self.resources.update();
+
+        let pending_adds = mem::replace(&mut self.pending_add, vec![]);
+        let pending_adds_was_0 = pending_adds.len() == 0;
+        self.pending_add = pending_adds.into_iter().filter_map(|pending_add| {
+            let is_some = {
+                pending_add.resources.value().is_some()
+            };
+            if is_some {
+                // Newly loaded resources may push usage over the memory budget
+                self.collect_resources = true;
+                let (resources, errors) = pending_add.resources.into_value();
+                if errors.len() > 0 {
+                    let message = errors.iter().map(|err| err.to_string()).collect::<Vec<String>>().join("\n");
+                    println!("Failed to load resources for entity {}: {}", pending_add.id, message);
+                    self.render_errors.push((pending_add.id, Some(message)));
+                } else {
+                    self.render_errors.push((pending_add.id, None));
+                }
+                self.renderer.add_node(RenderNode {
+                    id: pending_add.id,
+                    resources: resources,
+                    config: pending_add.config
+                });
+                return None;
+            } else {
+                return Some(pending_add);
+            }
+        }).collect();
+        if self.pending_add.len() == 0 && !pending_adds_was_0 && !self.first_load_timed {
+            self.first_load_timed = true;
+            println!("All entities added to renderer. {} ms", total_time.num_milliseconds());
+        }
+        let pending_mesh_updates = mem::replace(&mut self.pending_mesh_updates, vec![]);
+        for (entity_id, mesh) in pending_mesh_updates {
+            if mesh.value().is_none() {
+                self.pending_mesh_updates.push((entity_id, mesh));
+                continue;
+            }
+            match mesh.into_value() {
+                Ok(mesh) => {
+                    self.render_errors.push((entity_id, None));
+                    if !self.renderer.update_mesh(&entity_id, &mesh) {
+                        self.readd_entities.push(entity_id);
+                    }
+                },
+                Err(err) => {
+                    // Keep rendering the previous mesh
+                    println!("Failed to load mesh for entity {}: {}", entity_id, err);
+                    self.render_errors.push((entity_id, Some(err.to_string())));
+                }
+            }
+        }
+        let skybox_loaded = match self.pending_skybox {
+            Some(ref texture) => texture.value().is_some(),
+            None => false
+        };
+        if skybox_loaded {
+            self.collect_resources = true;
+            let texture = self.pending_skybox.take().unwrap().into_value();
+            let entity_id = self.skybox.as_ref().map(|&(entity_id, _)| entity_id).unwrap();
+            match texture {
+                Ok(texture) => {
+                    self.render_errors.push((entity_id, None));
+                    self.renderer.set_skybox(Some(Skybox {
+                        shader: self.skybox_shader.clone(),
+                        vertex_array: self.skybox_vertex_array.clone(),
+                        texture: texture
+                    }));
+                },
+                Err(err) => {
+                    println!("Failed to load skybox for entity {}: {}", entity_id, err);
+                    self.render_errors.push((entity_id, Some(err.to_string())));
+                    self.renderer.set_skybox(None);
+                }
+            }
+        }
+        if self.collect_resources {
+            self.collect_resources = false;
+            self.resources.collect_garbage();
+        }
+
+        self.target.bind();
+        self.renderer.render();
+        if let Some(path) = self.pending_screenshot.take() {
+            let (width, height) = self.target.size();
+            match self.renderer.read_color_buffer(width, height).save(&path) {
+                Ok(_) => println!("Saved screenshot to {:?}", path),
+                Err(err) => println!("Failed to save screenshot to {:?}: {:?}", path, err)
+            }
+        }
+        self.target.present();
+    }
+    pub fn set_memory_budget(&mut self, bytes: usize) {
+        self.resources.set_memory_budget(bytes);
+    }
+    pub fn resource_stats(&self) -> ResourceStats {
+        self.resources.stats()
+    }
+    pub fn read_color_buffer(&self) -> RgbaImage {
+        let (width, height) = self.target.size();
+        self.target.bind_for_read();
+        self.renderer.read_color_buffer(width, height)
+    }
+    pub fn read_depth_buffer(&self) -> RgbaImage {
+        let (width, height) = self.target.size();
+        self.target.bind_for_read();
+        self.renderer.read_depth_buffer(width, height)
+    }
+    pub fn screenshot(&mut self, path: &Path) {
+        self.pending_screenshot = Some(self.root_path.join(path));
+    }
 }
 
 impl ViewportSubSystem {
 
-    fn renderer_add(&mut self, document: &mut Document, entity_id: &EntityId) {
+    fn renderable_keys(document: &Document, entity_id: &EntityId) -> Result<Option<RenderableKeys>, PonTranslateErr> {
         let shader_key: Pon = match document.get_property(entity_id, "shader") {
             Ok(shader) => shader.clone(),
             Err(err) => Pon::String("basic".to_string())
         };
         let mesh_key: Pon = match document.get_property(entity_id, "mesh") {
             Ok(mesh) => mesh.clone(),
-            Err(err) => return ()
+            Err(err) => return Ok(None)
         };
         let texture_keys: Pon = match document.get_property(entity_id, "textures") {
             Ok(textures) => textures.clone(),
@@ -120,24 +302,61 @@ impl ViewportSubSystem {
                     Ok(diffuse) => Pon::Object(hashmap![
                         "diffuse".to_string() => diffuse.clone()
                     ]),
-                    Err(_) => return()
+                    Err(_) => return Ok(None)
                 }
             }
         };
 
         let mut texture_keys_vec = vec![];
         let mut texture_ids = vec![];
-        texture_keys.as_object(|hm| {
+        try!(texture_keys.as_object(|hm| {
             for (name, texture_key) in hm {
                 texture_ids.push(name.to_string());
                 texture_keys_vec.push(texture_key.clone());
             }
             Ok(())
-        }).unwrap();
+        }));
+
+        let mut textures = vec![];
+        for texture_key in texture_keys_vec {
+            textures.push(try!(texture_key.concretize()));
+        }
+        Ok(Some(RenderableKeys {
+            mesh: try!(mesh_key.concretize()),
+            shader: try!(shader_key.concretize()),
+            texture_ids: texture_ids,
+            textures: textures,
+            dynamic_mesh: match document.get_property(entity_id, "dynamic_mesh") {
+                Ok(dynamic_mesh) => try!(dynamic_mesh.translate::<bool>(&mut TranslateContext::empty())),
+                Err(_) => false
+            }
+        }))
+    }
+
+    fn renderer_add(&mut self, document: &mut Document, entity_id: &EntityId) {
+        let RenderableKeys { mesh: mesh_key, shader: shader_key, texture_ids, textures: texture_keys_vec, dynamic_mesh } =
+            match ViewportSubSystem::renderable_keys(document, entity_id) {
+                Ok(Some(keys)) => keys,
+                Ok(None) => return,
+                Err(err) => {
+                    println!("Invalid renderable entity {}: {:?}", entity_id, err);
+                    self.set_render_error(document, entity_id, Some(format!("{:?}", err)));
+                    return;
+                }
+            };
+        let mut resource_keys = vec![mesh_key.clone(), shader_key.clone()];
+        resource_keys.extend(texture_keys_vec.iter().cloned());
+        self.entity_resource_keys.insert(entity_id.clone(), resource_keys);
 
+        let resources = if dynamic_mesh {
+            self.dynamic_mesh_entities.insert(entity_id.clone());
+            self.resources.get_dynamic(document, mesh_key.clone(), shader_key.clone(), texture_keys_vec)
+        } else {
+            self.resources.get(document, mesh_key.clone(), shader_key.clone(), texture_keys_vec)
+        };
         self.pending_add.push(PendingAdd {
             id: entity_id.clone(),
-            resources: self.resources.get(document, mesh_key.clone(), shader_key.clone(), texture_keys_vec),
+            resources: resources,
             config: RenderNodeConfig {
                 texture_ids: texture_ids,
                 transform: match document.get_property(&entity_id, "transformed") {
@@ -151,13 +370,108 @@ impl ViewportSubSystem {
                 alpha: match document.get_property(&entity_id, "alpha") {
                     Ok(trans) => trans.translate::<bool>(&mut TranslateContext::empty()).unwrap_or(false),
                     Err(err) => false
+                },
+                draw_range: match document.get_property(&entity_id, "draw_range") {
+                    Ok(range) => range.translate::<DrawRange>(&mut TranslateContext::empty()).ok(),
+                    Err(err) => None
                 }
             }
         });
     }
+    /// The previous skybox keeps rendering until the new one is loaded.
+    fn skybox_changed(&mut self, document: &mut Document, entity_id: &EntityId) {
+        let key = match document.get_property(entity_id, "skybox") {
+            Ok(key) => key.concretize(),
+            Err(_) => {
+                if self.skybox.as_ref().map(|&(id, _)| id == *entity_id).unwrap_or(false) {
+                    self.skybox = None;
+                    self.pending_skybox = None;
+                    self.renderer.set_skybox(None);
+                    self.collect_resources = true;
+                }
+                return;
+            }
+        };
+        match key {
+            Ok(key) => {
+                self.pending_skybox = Some(self.resources.get_texture(document, &key));
+                self.skybox = Some((entity_id.clone(), key));
+            },
+            Err(err) => {
+                println!("Invalid skybox on entity {}: {:?}", entity_id, err);
+                self.set_render_error(document, entity_id, Some(format!("{:?}", err)));
+            }
+        }
+    }
     fn renderer_remove(&mut self, entity_id: &EntityId) {
         self.renderer.remove_node(entity_id);
         self.pending_add.retain(|p| p.id != *entity_id);
+        self.pending_mesh_updates.retain(|&(id, _)| id != *entity_id);
+        self.dynamic_mesh_entities.remove(entity_id);
+        self.entity_resource_keys.remove(entity_id);
+        self.collect_resources = true;
+    }
+    fn update_dynamic_mesh(&mut self, document: &mut Document, entity_id: &EntityId) -> bool {
+        if !self.dynamic_mesh_entities.contains(entity_id) || self.pending_add.iter().any(|p| p.id == *entity_id) {
+            return false;
+        }
+        match ViewportSubSystem::renderable_keys(document, entity_id) {
+            Ok(Some(ref keys)) if keys.dynamic_mesh => {
+                if let Some(resource_keys) = self.entity_resource_keys.get_mut(entity_id) {
+                    resource_keys[0] = keys.mesh.clone();
+                }
+                self.pending_mesh_updates.retain(|&(id, _)| id != *entity_id);
+                let mesh = self.resources.load_mesh(document, &keys.mesh);
+                self.pending_mesh_updates.push((entity_id.clone(), mesh));
+                true
+            },
+            _ => false
+        }
+    }
+    fn set_render_error(&mut self, document: &mut Document, entity_id: &EntityId, error: Option<String>) {
+        let value = match error {
+            Some(message) => {
+                self.entities_with_errors.insert(entity_id.clone());
+                Pon::String(message)
+            },
+            None => {
+                if !self.entities_with_errors.remove(entity_id) {
+                    return;
+                }
+                Pon::Nil
+            }
+        };
+        if let Err(err) = document.set_property(entity_id, "render_error", value) {
+            println!("Failed to set render_error on entity {}: {:?}", entity_id, err);
+        }
+    }
+    fn flush_render_errors(&mut self, document: &mut Document) {
+        let render_errors = mem::replace(&mut self.render_errors, vec![]);
+        for (entity_id, error) in render_errors {
+            self.set_render_error(document, &entity_id, error);
+        }
+    }
+    /// Affected entities keep rendering with their old resources until the new ones are loaded.
+    fn reload_changed_files(&mut self, document: &mut Document) {
+        let reloaded = self.resources.reload_changed_files(document);
+        if reloaded.len() == 0 {
+            return;
+        }
+        let entities: Vec<EntityId> = self.entity_resource_keys.iter()
+            .filter(|&(_, keys)| keys.iter().any(|key| reloaded.contains(key)))
+            .map(|(entity_id, _)| entity_id.clone())
+            .collect();
+        for entity_id in entities {
+            self.pending_add.retain(|p| p.id != entity_id);
+            self.renderer_add(document, &entity_id);
+        }
+        let skybox = match self.skybox {
+            Some((entity_id, ref key)) if reloaded.contains(key) => Some(entity_id),
+            _ => None
+        };
+        if let Some(entity_id) = skybox {
+            self.skybox_changed(document, &entity_id);
+        }
     }
 }
 
@@ -168,12 +482,23 @@ impl ISubSystem for ViewportSubSystem {
         //println!("CHANGED {:?}", prop_refs);
         let renderable_changed: HashSet<EntityId> = prop_refs.iter()
             .filter_map(|pr| {
-                if pr.property_key == "mesh" || pr.property_key == "diffuse" || pr.property_key == "alpha" || pr.property_key == "uniforms" {
+                if pr.property_key == "diffuse" || pr.property_key == "alpha" || pr.property_key == "uniforms" || pr.property_key == "dynamic_mesh" ||
+                    pr.property_key == "draw_range" {
                     return Some(pr.entity_id);
                 } else {
                     return None;
                 }
             }).collect();
+        let mesh_changed: HashSet<EntityId> = prop_refs.iter()
+            .filter(|pr| pr.property_key == "mesh" && !renderable_changed.contains(&pr.entity_id))
+            .map(|pr| pr.entity_id)
+            .collect();
+        for entity_id in mesh_changed {
+            if !self.update_dynamic_mesh(document, &entity_id) {
+                self.renderer_remove(&entity_id);
+                self.renderer_add(document, &entity_id);
+            }
+        }
         for entity_id in renderable_changed {
             self.renderer_remove(&entity_id);
             self.renderer_add(document, &entity_id);
@@ -185,6 +510,18 @@ impl ISubSystem for ViewportSubSystem {
             };
             self.renderer.set_transform(&pr.entity_id, transform);
         }
+        for pr in prop_refs.iter().filter(|pr| pr.property_key == "screenshot") {
+            match document.get_property(&pr.entity_id, "screenshot") {
+                Ok(path) => match path.translate::<String>(&mut TranslateContext::empty()) {
+                    Ok(path) => self.screenshot(Path::new(&path)),
+                    Err(err) => println!("Invalid screenshot path: {:?}", err)
+                },
+                Err(err) => {}
+            }
+        }
+        for pr in prop_refs.iter().filter(|pr| pr.property_key == "skybox") {
+            self.skybox_changed(document, &pr.entity_id);
+        }
         for pr in prop_refs.iter().filter(|pr| pr.property_key == "camera") {
             let camera = match document.get_property(&pr.entity_id, "camera") {
                 Ok(trans) => trans.translate(&mut TranslateContext::empty()).unwrap(),
@@ -197,45 +534,21 @@ impl ISubSystem for ViewportSubSystem {
     fn update(&mut self, system: &mut System) {
         let delta_time = time::get_time() - self.prev_time;
         self.prev_time = time::get_time();
-        let total_time = time::get_time() - self.start_time;
         self.fps_counter.add_frame(delta_time);
-        self.window.set_title(&format!("pyramid {}", self.fps_counter.to_string()));
+        self.target.set_title(&format!("pyramid {}", self.fps_counter.to_string()));
 
-        self.resources.update();
-
-        let pending_adds = mem::replace(&mut self.pending_add, vec![]);
-        let pending_adds_was_0 = pending_adds.len() == 0;
-        self.pending_add = pending_adds.into_iter().filter_map(|pending_add| {
-            let is_some = {
-                pending_add.resources.value().is_some()
-            };
-            if is_some {
-                self.renderer.add_node(RenderNode {
-                    id: pending_add.id,
-                    resources: pending_add.resources.into_value(),
-                    config: pending_add.config
-                });
-                return None;
-            } else {
-                return Some(pending_add);
-            }
-        }).collect();
-        if self.pending_add.len() == 0 && !pending_adds_was_0 && !self.first_load_timed {
-            self.first_load_timed = true;
-            println!("All entities added to renderer. {} ms", total_time.num_milliseconds());
+        self.reload_changed_files(system.document_mut());
+        let readd_entities = mem::replace(&mut self.readd_entities, vec![]);
+        for entity_id in readd_entities {
+            self.renderer_remove(&entity_id);
+            self.renderer_add(system.document_mut(), &entity_id);
         }
+        self.render_frame();
+        self.flush_render_errors(system.document_mut());
 
-        self.renderer.render();
-        self.window.swap_buffers();
-
-        for event in self.window.poll_events() {
-            match event {
-                glutin::Event::Closed => {
-                    system.exit();
-                    return;
-                },
-                _ => ()
-            }
+        if self.target.poll_closed() {
+            system.exit();
+            return;
         }
     }
 }
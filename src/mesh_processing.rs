@@ -0,0 +1,230 @@
+//! Steps that generate an attribute add it to the layout if it is missing, and overwrite it otherwise.
+
+use mesh::*;
+use pon_to_resource::ResourceErr;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::mem;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshProcess {
+    SmoothNormals,
+    FlatNormals,
+    Tangents,
+    Weld { epsilon: f32 },
+    FlipWinding
+}
+
+pub const DEFAULT_WELD_EPSILON: f32 = 1e-5;
+
+pub fn process_mesh(mut mesh: Mesh, steps: &[MeshProcess]) -> Result<Mesh, ResourceErr> {
+    if mesh.element_data.len() % 3 != 0 {
+        return Err(ResourceErr::Mesh("Only triangle meshes can be processed".to_string()));
+    }
+    if mesh.layout.stride == 0 || mesh.vertex_data.len() % mesh.layout.stride != 0 {
+        return Err(ResourceErr::Mesh(format!("{} values of vertex data do not fit a layout with stride {}", mesh.vertex_data.len(), mesh.layout.stride)));
+    }
+    let n = n_vertices(&mesh);
+    if let Some(&index) = mesh.element_data.iter().find(|&&index| index as usize >= n) {
+        return Err(ResourceErr::Mesh(format!("Index {} is out of range for {} vertices", index, n)));
+    }
+    for step in steps {
+        mesh = match *step {
+            MeshProcess::SmoothNormals => try!(smooth_normals(mesh)),
+            MeshProcess::FlatNormals => try!(flat_normals(mesh)),
+            MeshProcess::Tangents => try!(tangents(mesh)),
+            MeshProcess::Weld { epsilon } => weld(mesh, epsilon),
+            MeshProcess::FlipWinding => flip_winding(mesh)
+        };
+    }
+    Ok(mesh)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = dot(a, a).sqrt();
+    if len == 0.0 { a } else { [a[0] / len, a[1] / len, a[2] / len] }
+}
+
+fn n_vertices(mesh: &Mesh) -> usize {
+    if mesh.layout.stride == 0 { 0 } else { mesh.vertex_data.len() / mesh.layout.stride }
+}
+
+fn find_attribute(layout: &Layout, name: &str) -> Option<(usize, usize)> {
+    layout.attributes.iter().find(|a| a.name == name).map(|a| (a.offset, a.size))
+}
+
+fn read_attribute(mesh: &Mesh, attribute: (usize, usize), vertex: usize) -> [f32; 3] {
+    let mut value = [0.0; 3];
+    let start = vertex * mesh.layout.stride + attribute.0;
+    for i in 0..cmp::min(attribute.1, 3) {
+        value[i] = mesh.vertex_data[start + i];
+    }
+    value
+}
+
+fn write_attribute(mesh: &mut Mesh, attribute: (usize, usize), vertex: usize, value: &[f32]) {
+    let start = vertex * mesh.layout.stride + attribute.0;
+    for i in 0..cmp::min(attribute.1, value.len()) {
+        mesh.vertex_data[start + i] = value[i];
+    }
+}
+
+fn required_attribute(mesh: &Mesh, name: &str, step: &str) -> Result<(usize, usize), ResourceErr> {
+    find_attribute(&mesh.layout, name)
+        .ok_or_else(|| ResourceErr::Mesh(format!("{} needs a {} attribute", step, name)))
+}
+
+fn with_attribute(mesh: Mesh, name: &str, size: usize) -> (Mesh, (usize, usize)) {
+    if let Some(attribute) = find_attribute(&mesh.layout, name) {
+        return (mesh, attribute);
+    }
+    let mut specs: Vec<AttributeSpec> = mesh.layout.attributes.iter().map(|a| AttributeSpec(a.name.clone(), a.size)).collect();
+    specs.push(AttributeSpec(name.to_string(), size));
+    let layout = Layout::new(specs);
+    let old_stride = mesh.layout.stride;
+    let mut vertex_data = Vec::with_capacity(n_vertices(&mesh) * layout.stride);
+    for vertex in mesh.vertex_data.chunks(old_stride) {
+        vertex_data.extend(vertex.iter().cloned());
+        for _ in old_stride..layout.stride {
+            vertex_data.push(0.0);
+        }
+    }
+    let attribute = find_attribute(&layout, name).unwrap();
+    (Mesh { layout: layout, vertex_data: vertex_data, element_data: mesh.element_data }, attribute)
+}
+
+fn smooth_normals(mesh: Mesh) -> Result<Mesh, ResourceErr> {
+    let position = try!(required_attribute(&mesh, "position", "smooth_normals"));
+    let (mut mesh, normal) = with_attribute(mesh, "normal", 3);
+    // Vertices at the same position are smoothed together, even if they differ in other attributes
+    let mut position_normals: HashMap<[u32; 3], [f32; 3]> = HashMap::new();
+    let key = |p: [f32; 3]| -> [u32; 3] { unsafe { mem::transmute(p) } };
+    for triangle in mesh.element_data.chunks(3) {
+        let p: Vec<[f32; 3]> = triangle.iter().map(|&i| read_attribute(&mesh, position, i as usize)).collect();
+        // The length of the cross product is twice the area, which weights the normals
+        let face = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+        for &vertex in p.iter() {
+            let n = position_normals.entry(key(vertex)).or_insert([0.0; 3]);
+            *n = [n[0] + face[0], n[1] + face[1], n[2] + face[2]];
+        }
+    }
+    for vertex in 0..n_vertices(&mesh) {
+        let n = match position_normals.get(&key(read_attribute(&mesh, position, vertex))) {
+            Some(n) => normalize(*n),
+            None => continue
+        };
+        write_attribute(&mut mesh, normal, vertex, &n);
+    }
+    Ok(mesh)
+}
+
+fn flat_normals(mesh: Mesh) -> Result<Mesh, ResourceErr> {
+    try!(required_attribute(&mesh, "position", "flat_normals"));
+    let (mesh, normal) = with_attribute(mesh, "normal", 3);
+    let position = find_attribute(&mesh.layout, "position").unwrap();
+    let stride = mesh.layout.stride;
+    let mut flat = Mesh {
+        layout: mesh.layout.clone(),
+        vertex_data: Vec::with_capacity(mesh.element_data.len() * stride),
+        element_data: (0..mesh.element_data.len() as u32).collect()
+    };
+    for triangle in mesh.element_data.chunks(3) {
+        let p: Vec<[f32; 3]> = triangle.iter().map(|&i| read_attribute(&mesh, position, i as usize)).collect();
+        let face = normalize(cross(sub(p[1], p[0]), sub(p[2], p[0])));
+        for &i in triangle {
+            let first = flat.vertex_data.len() / stride;
+            let start = i as usize * stride;
+            flat.vertex_data.extend(mesh.vertex_data[start..(start + stride)].iter().cloned());
+            write_attribute(&mut flat, normal, first, &face);
+        }
+    }
+    Ok(flat)
+}
+
+fn tangents(mesh: Mesh) -> Result<Mesh, ResourceErr> {
+    let position = try!(required_attribute(&mesh, "position", "tangents"));
+    let texcoord = try!(required_attribute(&mesh, "texcoord", "tangents"));
+    let normal = try!(required_attribute(&mesh, "normal", "tangents"));
+    let (mut mesh, tangent) = with_attribute(mesh, "tangent", 4);
+    let n = n_vertices(&mesh);
+    let mut tangents = vec![[0.0f32; 3]; n];
+    let mut bitangents = vec![[0.0f32; 3]; n];
+    for triangle in mesh.element_data.chunks(3) {
+        let p: Vec<[f32; 3]> = triangle.iter().map(|&i| read_attribute(&mesh, position, i as usize)).collect();
+        let uv: Vec<[f32; 3]> = triangle.iter().map(|&i| read_attribute(&mesh, texcoord, i as usize)).collect();
+        let (e1, e2) = (sub(p[1], p[0]), sub(p[2], p[0]));
+        let (du1, dv1, du2, dv2) = (uv[1][0] - uv[0][0], uv[1][1] - uv[0][1], uv[2][0] - uv[0][0], uv[2][1] - uv[0][1]);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let r = 1.0 / det;
+        let t = [(e1[0] * dv2 - e2[0] * dv1) * r, (e1[1] * dv2 - e2[1] * dv1) * r, (e1[2] * dv2 - e2[2] * dv1) * r];
+        let bt = [(e2[0] * du1 - e1[0] * du2) * r, (e2[1] * du1 - e1[1] * du2) * r, (e2[2] * du1 - e1[2] * du2) * r];
+        // Weighting by the corner angle makes the result independent of how faces are triangulated
+        for corner in 0..3 {
+            let (ea, eb) = (sub(p[(corner + 1) % 3], p[corner]), sub(p[(corner + 2) % 3], p[corner]));
+            let angle = dot(normalize(ea), normalize(eb)).max(-1.0).min(1.0).acos();
+            let vertex = triangle[corner] as usize;
+            for c in 0..3 {
+                tangents[vertex][c] += t[c] * angle;
+                bitangents[vertex][c] += bt[c] * angle;
+            }
+        }
+    }
+    for vertex in 0..n {
+        let nv = normalize(read_attribute(&mesh, normal, vertex));
+        let t = tangents[vertex];
+        // Gram-Schmidt orthogonalization against the normal
+        let d = dot(nv, t);
+        let t = normalize([t[0] - nv[0] * d, t[1] - nv[1] * d, t[2] - nv[2] * d]);
+        let w = if dot(cross(nv, t), bitangents[vertex]) < 0.0 { -1.0 } else { 1.0 };
+        write_attribute(&mut mesh, tangent, vertex, &[t[0], t[1], t[2], w]);
+    }
+    Ok(mesh)
+}
+
+fn weld(mesh: Mesh, epsilon: f32) -> Mesh {
+    let stride = mesh.layout.stride;
+    let scale = if epsilon > 0.0 { 1.0 / epsilon } else { 1.0 / DEFAULT_WELD_EPSILON };
+    let mut unique: HashMap<Vec<i64>, u32> = HashMap::new();
+    let mut remap = Vec::with_capacity(n_vertices(&mesh));
+    let mut vertex_data = vec![];
+    for vertex in mesh.vertex_data.chunks(stride) {
+        let key: Vec<i64> = vertex.iter().map(|v| (v * scale).round() as i64).collect();
+        let index = *unique.entry(key).or_insert_with(|| {
+            vertex_data.extend(vertex.iter().cloned());
+            (vertex_data.len() / stride - 1) as u32
+        });
+        remap.push(index);
+    }
+    let mut element_data = Vec::with_capacity(mesh.element_data.len());
+    for triangle in mesh.element_data.chunks(3) {
+        let (a, b, c) = (remap[triangle[0] as usize], remap[triangle[1] as usize], remap[triangle[2] as usize]);
+        // Welding can collapse small triangles
+        if a != b && b != c && c != a {
+            element_data.extend([a, b, c].iter().cloned());
+        }
+    }
+    Mesh { layout: mesh.layout, vertex_data: vertex_data, element_data: element_data }
+}
+
+fn flip_winding(mut mesh: Mesh) -> Mesh {
+    for triangle in mesh.element_data.chunks_mut(3) {
+        triangle.swap(1, 2);
+    }
+    mesh
+}
@@ -0,0 +1,183 @@
+//! A loader for Wavefront OBJ files. Faces are triangulated as fans, and materials are ignored.
+
+use mesh::*;
+use pon_to_resource::ResourceErr;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexRef {
+    position: usize,
+    texcoord: Option<usize>,
+    normal: Option<usize>
+}
+
+struct ObjData {
+    positions: Vec<[f32; 3]>,
+    texcoords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    triangles: Vec<(String, [VertexRef; 3])>
+}
+
+fn parse_floats(parts: &[&str], n: usize, line: usize) -> Result<Vec<f32>, String> {
+    if parts.len() < n {
+        return Err(format!("line {}: expected {} values", line, n));
+    }
+    parts[0..n].iter().map(|p| p.parse::<f32>().map_err(|_| format!("line {}: invalid number {}", line, p))).collect()
+}
+
+/// OBJ indices are 1-based, and negative indices count from the end of the list.
+fn parse_index(index: &str, len: usize, line: usize) -> Result<usize, String> {
+    let i = try!(index.parse::<i64>().map_err(|_| format!("line {}: invalid index {}", line, index)));
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("line {}: index {} out of range", line, index));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_vertex_ref(vertex: &str, data: &ObjData, line: usize) -> Result<VertexRef, String> {
+    let indices: Vec<&str> = vertex.split('/').collect();
+    let position = try!(parse_index(indices[0], data.positions.len(), line));
+    let texcoord = match indices.get(1) {
+        Some(i) if i.len() > 0 => Some(try!(parse_index(i, data.texcoords.len(), line))),
+        _ => None
+    };
+    let normal = match indices.get(2) {
+        Some(i) if i.len() > 0 => Some(try!(parse_index(i, data.normals.len(), line))),
+        _ => None
+    };
+    Ok(VertexRef { position: position, texcoord: texcoord, normal: normal })
+}
+
+fn parse_obj<R: BufRead>(reader: R) -> Result<ObjData, String> {
+    let mut data = ObjData { positions: vec![], texcoords: vec![], normals: vec![], triangles: vec![] };
+    let mut group = "default".to_string();
+    for (i, line) in reader.lines().enumerate() {
+        let line = try!(line.map_err(|err| format!("{:?}", err)));
+        let line_number = i + 1;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() == 0 {
+            continue;
+        }
+        match parts[0] {
+            "v" => {
+                let v = try!(parse_floats(&parts[1..], 3, line_number));
+                data.positions.push([v[0], v[1], v[2]]);
+            },
+            "vt" => {
+                let v = try!(parse_floats(&parts[1..], 2, line_number));
+                data.texcoords.push([v[0], v[1]]);
+            },
+            "vn" => {
+                let v = try!(parse_floats(&parts[1..], 3, line_number));
+                data.normals.push([v[0], v[1], v[2]]);
+            },
+            "f" => {
+                if parts.len() < 4 {
+                    return Err(format!("line {}: a face needs at least 3 vertices", line_number));
+                }
+                let mut vertices = vec![];
+                for vertex in &parts[1..] {
+                    vertices.push(try!(parse_vertex_ref(vertex, &data, line_number)));
+                }
+                for j in 1..(vertices.len() - 1) {
+                    data.triangles.push((group.clone(), [vertices[0], vertices[j], vertices[j + 1]]));
+                }
+            },
+            "g" | "o" => {
+                group = if parts.len() > 1 { parts[1..].join(" ") } else { "default".to_string() };
+            },
+            _ => {}
+        }
+    }
+    Ok(data)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    if len == 0.0 { a } else { [a[0] / len, a[1] / len, a[2] / len] }
+}
+
+/// Vertices without a normal get the average normal of the faces they are part of.
+fn obj_to_mesh(data: &ObjData, group: Option<&str>) -> Result<Mesh, String> {
+    let mut vertex_refs: Vec<VertexRef> = vec![];
+    let mut vertex_index: HashMap<VertexRef, u32> = HashMap::new();
+    let mut generated_normals: Vec<[f32; 3]> = vec![];
+    let mut element_data = vec![];
+    for &(ref triangle_group, ref triangle) in &data.triangles {
+        if let Some(group) = group {
+            if triangle_group != group {
+                continue;
+            }
+        }
+        let face_normal = cross(
+            sub(data.positions[triangle[1].position], data.positions[triangle[0].position]),
+            sub(data.positions[triangle[2].position], data.positions[triangle[0].position]));
+        for vertex in triangle {
+            let index = *vertex_index.entry(*vertex).or_insert_with(|| {
+                vertex_refs.push(*vertex);
+                generated_normals.push([0.0, 0.0, 0.0]);
+                (vertex_refs.len() - 1) as u32
+            });
+            let n = &mut generated_normals[index as usize];
+            n[0] += face_normal[0];
+            n[1] += face_normal[1];
+            n[2] += face_normal[2];
+            element_data.push(index);
+        }
+    }
+    if element_data.len() == 0 {
+        return Err(match group {
+            Some(group) => format!("No faces in group {}", group),
+            None => "No faces".to_string()
+        });
+    }
+
+    let layout = Layout::position_texcoord_normal();
+    let mut vertex_data = vec![0.0; vertex_refs.len() * layout.stride];
+    for (i, vertex) in vertex_refs.iter().enumerate() {
+        let texcoord = vertex.texcoord.map(|t| data.texcoords[t]).unwrap_or([0.0, 0.0]);
+        let normal = match vertex.normal {
+            Some(n) => data.normals[n],
+            None => normalize(generated_normals[i])
+        };
+        for attribute in &layout.attributes {
+            let values: &[f32] = match attribute.name.as_str() {
+                "position" => &data.positions[vertex.position],
+                "texcoord" => &texcoord,
+                "normal" => &normal,
+                _ => continue
+            };
+            let start = i * layout.stride + attribute.offset;
+            for j in 0..cmp::min(attribute.size, values.len()) {
+                vertex_data[start + j] = values[j];
+            }
+        }
+    }
+    Ok(Mesh {
+        layout: layout,
+        vertex_data: vertex_data,
+        element_data: element_data
+    })
+}
+
+pub fn load_obj(path: &Path, group: Option<&str>) -> Result<Mesh, ResourceErr> {
+    println!("Loading obj {:?}", path);
+    let file = try!(File::open(path).map_err(|err| ResourceErr::io(path, err)));
+    let data = try!(parse_obj(BufReader::new(file)).map_err(|err| ResourceErr::Io { path: path.to_path_buf(), message: err }));
+    obj_to_mesh(&data, group).map_err(|err| ResourceErr::Io { path: path.to_path_buf(), message: err })
+}
@@ -0,0 +1,104 @@
+//! A binary mesh format that loads with minimal parsing. All values are little endian:
+//!
+//! ```text
+//! magic            b"PMSH"
+//! version          u32 (currently 1)
+//! n_attributes     u32
+//!   name_length    u32
+//!   name           name_length bytes of utf-8
+//!   size           u32, floats per vertex for this attribute
+//! n_vertex_floats  u32
+//! n_indices        u32
+//! vertex_data      n_vertex_floats f32
+//! element_data     n_indices u32
+//! ```
+
+use mesh::*;
+use pon_to_resource::ResourceErr;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+pub const MAGIC: &'static [u8; 4] = b"PMSH";
+pub const VERSION: u32 = 1;
+
+pub fn write_pmesh<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    try!(writer.write_all(MAGIC));
+    try!(writer.write_u32::<LittleEndian>(VERSION));
+    try!(writer.write_u32::<LittleEndian>(mesh.layout.attributes.len() as u32));
+    for attribute in &mesh.layout.attributes {
+        try!(writer.write_u32::<LittleEndian>(attribute.name.len() as u32));
+        try!(writer.write_all(attribute.name.as_bytes()));
+        try!(writer.write_u32::<LittleEndian>(attribute.size as u32));
+    }
+    try!(writer.write_u32::<LittleEndian>(mesh.vertex_data.len() as u32));
+    try!(writer.write_u32::<LittleEndian>(mesh.element_data.len() as u32));
+    for v in &mesh.vertex_data {
+        try!(writer.write_f32::<LittleEndian>(*v));
+    }
+    for i in &mesh.element_data {
+        try!(writer.write_u32::<LittleEndian>(*i));
+    }
+    Ok(())
+}
+
+pub fn read_pmesh(data: &[u8]) -> Result<Mesh, String> {
+    let mut rdr = data;
+    if rdr.len() < 4 || &rdr[0..4] != MAGIC {
+        return Err("not a pmesh file".to_string());
+    }
+    rdr = &rdr[4..];
+    let version = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+    if version != VERSION {
+        return Err(format!("unsupported pmesh version {}", version));
+    }
+    let n_attributes = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+    let mut attributes = vec![];
+    for _ in 0..n_attributes {
+        let name_length = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as usize;
+        if rdr.len() < name_length {
+            return Err("file is truncated".to_string());
+        }
+        let name = try!(String::from_utf8(rdr[0..name_length].to_vec()).map_err(|_| "attribute name is not valid utf-8".to_string()));
+        rdr = &rdr[name_length..];
+        let size = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as usize;
+        attributes.push(AttributeSpec(name, size));
+    }
+    let n_vertex_floats = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as usize;
+    let n_indices = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as usize;
+    if rdr.len() != (n_vertex_floats + n_indices) * 4 {
+        return Err(format!("expected {} bytes of mesh data, found {}", (n_vertex_floats + n_indices) * 4, rdr.len()));
+    }
+    let mut vertex_data = Vec::with_capacity(n_vertex_floats);
+    for _ in 0..n_vertex_floats {
+        vertex_data.push(rdr.read_f32::<LittleEndian>().unwrap());
+    }
+    let mut element_data = Vec::with_capacity(n_indices);
+    for _ in 0..n_indices {
+        element_data.push(rdr.read_u32::<LittleEndian>().unwrap());
+    }
+    let layout = Layout::new(attributes);
+    if layout.stride == 0 || vertex_data.len() % layout.stride != 0 {
+        return Err("vertex data does not match the layout".to_string());
+    }
+    let n_vertices = (vertex_data.len() / layout.stride) as u32;
+    if element_data.iter().any(|&i| i >= n_vertices) {
+        return Err("index out of range".to_string());
+    }
+    Ok(Mesh {
+        layout: layout,
+        vertex_data: vertex_data,
+        element_data: element_data
+    })
+}
+
+pub fn load_pmesh(path: &Path) -> Result<Mesh, ResourceErr> {
+    println!("Loading pmesh {:?}", path);
+    let mut file = try!(File::open(path).map_err(|err| ResourceErr::io(path, err)));
+    let mut data = vec![];
+    try!(file.read_to_end(&mut data).map_err(|err| ResourceErr::io(path, err)));
+    read_pmesh(&data).map_err(|err| ResourceErr::Io { path: path.to_path_buf(), message: err })
+}
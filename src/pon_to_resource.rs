@@ -1,6 +1,15 @@
 extern crate image;
 
 use image::RgbaImage;
+use obj_loader;
+use gltf;
+use pmesh;
+use procedural_textures::*;
+use primitives::*;
+use mesh_processing::*;
+use heightmap::*;
+use dynamic_texture::DynamicTexture;
+use gltf::GltfRef;
 use pyramid::pon::*;
 use pyramid::document::*;
 use mesh::*;
@@ -17,13 +26,49 @@ use std::borrow::Cow;
 use std::rc::Rc;
 use ppromise::*;
 use std::mem;
+use std::fmt;
 
 #[derive(Debug)]
 pub struct ShaderSource {
     pub vertex_src: String,
     pub vertex_debug_source_name: String,
     pub fragment_src: String,
-    pub fragment_debug_source_name: String
+    pub fragment_debug_source_name: String,
+    pub source_files: Vec<PathBuf>
+}
+
+/// Errors have to be cloneable since they are shared through promises.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceErr {
+    Translate(String),
+    Io { path: PathBuf, message: String },
+    Shader(String),
+    Mesh(String),
+    Texture(String)
+}
+
+impl ResourceErr {
+    pub fn io<E: fmt::Debug>(path: &Path, err: E) -> ResourceErr {
+        ResourceErr::Io { path: path.to_path_buf(), message: format!("{:?}", err) }
+    }
+}
+
+impl From<PonTranslateErr> for ResourceErr {
+    fn from(err: PonTranslateErr) -> ResourceErr {
+        ResourceErr::Translate(format!("{:?}", err))
+    }
+}
+
+impl fmt::Display for ResourceErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ResourceErr::Translate(ref message) => write!(f, "{}", message),
+            &ResourceErr::Io { ref path, ref message } => write!(f, "{:?}: {}", path, message),
+            &ResourceErr::Shader(ref message) => write!(f, "{}", message),
+            &ResourceErr::Mesh(ref message) => write!(f, "{}", message),
+            &ResourceErr::Texture(ref message) => write!(f, "{}", message)
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -32,23 +77,415 @@ pub enum Texture {
     Floats {
         width: u32,
         height: u32,
+        channels: u32,
+        half: bool,
         data: Vec<f32>
+    },
+    /// Six square faces, in the order +X, -X, +Y, -Y, +Z, -Z.
+    Cubemap(Vec<RgbaImage>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureSampler {
+    pub min_filter: TextureFilter,
+    pub mag_filter: TextureFilter,
+    pub mipmaps: bool,
+    pub wrap_s: TextureWrap,
+    pub wrap_t: TextureWrap,
+    pub anisotropy: f32
+}
+
+impl Default for TextureSampler {
+    fn default() -> TextureSampler {
+        TextureSampler {
+            min_filter: TextureFilter::Linear,
+            mag_filter: TextureFilter::Linear,
+            mipmaps: false,
+            wrap_s: TextureWrap::Repeat,
+            wrap_t: TextureWrap::Repeat,
+            anisotropy: 1.0
+        }
+    }
+}
+
+impl Translatable<TextureFilter> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<TextureFilter, PonTranslateErr> {
+        let value = try!(self.translate::<String>(context));
+        match value.as_str() {
+            "nearest" => Ok(TextureFilter::Nearest),
+            "linear" => Ok(TextureFilter::Linear),
+            _ => Err(PonTranslateErr::InvalidValue { value: value.clone() })
+        }
+    }
+}
+
+impl Translatable<TextureWrap> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<TextureWrap, PonTranslateErr> {
+        let value = try!(self.translate::<String>(context));
+        match value.as_str() {
+            "repeat" => Ok(TextureWrap::Repeat),
+            "mirrored_repeat" => Ok(TextureWrap::MirroredRepeat),
+            "clamp_to_edge" => Ok(TextureWrap::ClampToEdge),
+            _ => Err(PonTranslateErr::InvalidValue { value: value.clone() })
+        }
+    }
+}
+
+/// Meshes keep their vertex data as floats, which are converted when uploaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentType {
+    Float,
+    HalfFloat,
+    UnsignedByteNormalized,
+    UnsignedShortNormalized,
+    UnsignedByte,
+    UnsignedShort,
+    Int
+}
+
+impl Translatable<ComponentType> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<ComponentType, PonTranslateErr> {
+        let value = try!(self.translate::<String>(context));
+        match value.as_str() {
+            "f32" => Ok(ComponentType::Float),
+            "f16" => Ok(ComponentType::HalfFloat),
+            "u8_norm" => Ok(ComponentType::UnsignedByteNormalized),
+            "u16_norm" => Ok(ComponentType::UnsignedShortNormalized),
+            "u8" => Ok(ComponentType::UnsignedByte),
+            "u16" => Ok(ComponentType::UnsignedShort),
+            "i32" => Ok(ComponentType::Int),
+            _ => Err(PonTranslateErr::InvalidValue { value: value.clone() })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VertexFormat(pub Vec<(String, ComponentType)>);
+
+impl VertexFormat {
+    pub fn component_type(&self, attribute: &str) -> ComponentType {
+        self.0.iter().find(|&&(ref name, _)| name == attribute).map(|&(_, ty)| ty).unwrap_or(ComponentType::Float)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeshTopology {
+    Triangles,
+    TriangleStrip,
+    Lines,
+    LineStrip,
+    Points
+}
+
+impl Default for MeshTopology {
+    fn default() -> MeshTopology {
+        MeshTopology::Triangles
+    }
+}
+
+impl Translatable<MeshTopology> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<MeshTopology, PonTranslateErr> {
+        let value = try!(self.translate::<String>(context));
+        match value.as_str() {
+            "triangles" => Ok(MeshTopology::Triangles),
+            "triangle_strip" => Ok(MeshTopology::TriangleStrip),
+            "lines" => Ok(MeshTopology::Lines),
+            "line_strip" => Ok(MeshTopology::LineStrip),
+            "points" => Ok(MeshTopology::Points),
+            _ => Err(PonTranslateErr::InvalidValue { value: value.clone() })
+        }
     }
 }
 
-pub struct LocalAttributeSpec(AttributeSpec);
+pub struct LocalAttributeSpec(AttributeSpec, ComponentType);
 
 impl Translatable<LocalAttributeSpec> for Pon {
     fn inner_translate(&self, context: &mut TranslateContext) -> Result<LocalAttributeSpec, PonTranslateErr> {
         self.as_array(|p| {
-                Ok(LocalAttributeSpec(AttributeSpec(try!(p[0].translate::<String>(context)), try!(p[1].translate::<i64>(context)) as usize)))
+            let spec = AttributeSpec(try!(p[0].translate::<String>(context)), try!(p[1].translate::<i64>(context)) as usize);
+            let component_type = match p.get(2) {
+                Some(ty) => try!(ty.translate::<ComponentType>(context)),
+                None => ComponentType::Float
+            };
+            Ok(LocalAttributeSpec(spec, component_type))
         })
     }
 }
 
-pub fn pon_to_mesh(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<Rc<Mesh>, PonTranslateErr> {
+pub trait LoadableMesh {
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>>;
+    fn load_processed(&mut self, async_runner: &mut AsyncRunner, process: Vec<MeshProcess>) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        self.load(async_runner).then_move(move |mesh| mesh.and_then(|mesh| {
+            let mesh = Mesh {
+                layout: mesh.layout.clone(),
+                vertex_data: mesh.vertex_data.clone(),
+                element_data: mesh.element_data.clone()
+            };
+            process_mesh(mesh, &process).map(|mesh| Rc::new(mesh))
+        }))
+    }
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+    fn vertex_format(&self) -> VertexFormat {
+        VertexFormat::default()
+    }
+    fn topology(&self) -> MeshTopology {
+        MeshTopology::Triangles
+    }
+}
+pub trait MeshBuilder : Send {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr>;
+}
+struct AsyncMesh {
+    builder: Option<Box<MeshBuilder>>
+}
+impl AsyncMesh {
+    fn new(builder: Box<MeshBuilder>) -> Box<LoadableMesh> {
+        Box::new(AsyncMesh { builder: Some(builder) })
+    }
+}
+impl LoadableMesh for AsyncMesh {
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        self.load_processed(async_runner, vec![])
+    }
+    fn load_processed(&mut self, async_runner: &mut AsyncRunner, process: Vec<MeshProcess>) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        let builder = mem::replace(&mut self.builder, None).unwrap();
+        async_runner.exec_async(move || builder.build().and_then(|mesh| process_mesh(mesh, &process)))
+            .then_move(|mesh| mesh.map(|mesh| Rc::new(mesh)))
+    }
+}
+struct MeshWithOptions {
+    mesh: Box<LoadableMesh>,
+    process: Vec<MeshProcess>,
+    format: VertexFormat,
+    topology: MeshTopology
+}
+impl LoadableMesh for MeshWithOptions {
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        self.mesh.load_processed(async_runner, self.process.clone())
+    }
+    fn source_files(&self) -> Vec<PathBuf> {
+        self.mesh.source_files()
+    }
+    fn vertex_format(&self) -> VertexFormat {
+        self.format.clone()
+    }
+    fn topology(&self) -> MeshTopology {
+        self.topology
+    }
+}
+struct ResourceMesh {
+    mesh: Rc<Mesh>
+}
+impl LoadableMesh for ResourceMesh {
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        Promise::resolved(Ok(self.mesh.clone()))
+    }
+}
+struct StaticMeshBuilder {
+    layout: Layout,
+    vertices: Vec<f32>,
+    indices: Vec<i64>
+}
+impl MeshBuilder for StaticMeshBuilder {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        let StaticMeshBuilder { layout, vertices, indices } = *self;
+        Ok(Mesh {
+            layout: layout,
+            vertex_data: vertices,
+            element_data: indices.iter().map(|x| *x as u32).collect()
+        })
+    }
+}
+struct MeshFromFile {
+    path: PathBuf,
+    group: Option<String>
+}
+impl LoadableMesh for MeshFromFile {
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        self.load_processed(async_runner, vec![])
+    }
+    fn load_processed(&mut self, async_runner: &mut AsyncRunner, process: Vec<MeshProcess>) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        let path = self.path.clone();
+        let group = self.group.clone();
+        async_runner.exec_async(move || mesh_from_file(&path, group.as_ref().map(|g| g.as_str()))
+                .and_then(|mesh| process_mesh(mesh, &process)))
+            .then_move(|mesh| mesh.map(|mesh| Rc::new(mesh)))
+    }
+}
+
+pub fn mesh_from_file(path: &Path, group: Option<&str>) -> Result<Mesh, ResourceErr> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("obj") => obj_loader::load_obj(path, group),
+        Some("gltf") | Some("glb") => gltf::load_mesh(path, &GltfRef::Index(0), 0),
+        Some("pmesh") => pmesh::load_pmesh(path),
+        _ => Err(ResourceErr::Io { path: path.to_path_buf(), message: "Unsupported mesh file format".to_string() })
+    }
+}
+
+struct HeightmapMesh {
+    path: PathBuf,
+    options: TerrainOptions
+}
+impl LoadableMesh for HeightmapMesh {
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        self.load_processed(async_runner, vec![])
+    }
+    fn load_processed(&mut self, async_runner: &mut AsyncRunner, process: Vec<MeshProcess>) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        let path = self.path.clone();
+        let options = self.options.clone();
+        async_runner.exec_async(move || {
+                let heightmap = try!(load_heightmap(&path));
+                let mesh = try!(heightmap_to_mesh(&heightmap, &options).map_err(|err| ResourceErr::Io { path: path.clone(), message: err }));
+                process_mesh(mesh, &process)
+            })
+            .then_move(|mesh| mesh.map(|mesh| Rc::new(mesh)))
+    }
+}
+
+#[derive(Clone)]
+enum GltfMeshSource {
+    Mesh(GltfRef),
+    Node(GltfRef)
+}
+struct GltfMesh {
+    path: PathBuf,
+    source: GltfMeshSource,
+    primitive: usize
+}
+impl LoadableMesh for GltfMesh {
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        self.load_processed(async_runner, vec![])
+    }
+    fn load_processed(&mut self, async_runner: &mut AsyncRunner, process: Vec<MeshProcess>) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        let path = self.path.clone();
+        let primitive = self.primitive;
+        let source = self.source.clone();
+        async_runner.exec_async(move || match source {
+                GltfMeshSource::Mesh(ref mesh) => gltf::load_mesh(&path, mesh, primitive),
+                GltfMeshSource::Node(ref node) => gltf::load_node_mesh(&path, node, primitive)
+            }.and_then(|mesh| process_mesh(mesh, &process)))
+            .then_move(|mesh| mesh.map(|mesh| Rc::new(mesh)))
+    }
+}
+
+fn pon_to_gltf_ref(data: &Pon, field: &str, context: &mut TranslateContext) -> Result<GltfRef, PonTranslateErr> {
+    match data.field_as::<String>(field, context) {
+        Ok(name) => Ok(GltfRef::Name(name)),
+        Err(_) => Ok(GltfRef::Index(try!(data.field_as::<i64>(field, context)) as usize))
+    }
+}
+
+impl MeshBuilder for Grid {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok((*self).into())
+    }
+}
+impl MeshBuilder for Box3 {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok((*self).into())
+    }
+}
+
+fn layout_or_default(data: &Pon, context: &mut TranslateContext) -> Layout {
+    match data.field_as::<PonAutoVec<LocalAttributeSpec>>("layout", context) {
+        Ok(attrs) => {
+            let attribs = attrs.0.into_iter().map(|x| x.0).collect();
+            Layout::new(attribs)
+        },
+        Err(_) => Layout::position_texcoord_normal()
+    }
+}
+
+fn count_field(data: &Pon, field: &str, default: i64, minimum: i64, context: &mut TranslateContext) -> Result<u32, PonTranslateErr> {
+    let value = try!(data.field_as_or::<i64>(field, default, context));
+    if value < minimum || value > u32::max_value() as i64 {
+        return Err(PonTranslateErr::InvalidValue { value: format!("{}: {}, must be between {} and {}", field, value, minimum, u32::max_value()) });
+    }
+    Ok(value as u32)
+}
+
+fn pon_to_vertex_format(node: &Pon, context: &mut TranslateContext) -> Result<VertexFormat, PonTranslateErr> {
+    let layout = match node.as_typed(|&TypedPon { ref data, .. }| Ok(data.field("layout").ok().cloned())) {
+        Ok(Some(layout)) => try!(layout.translate::<PonAutoVec<LocalAttributeSpec>>(context)),
+        _ => return Ok(VertexFormat::default())
+    };
+    Ok(VertexFormat(layout.0.into_iter()
+        .filter(|spec| spec.1 != ComponentType::Float)
+        .map(|spec| ((spec.0).0, spec.1))
+        .collect()))
+}
+
+fn pon_to_mesh_process(node: &Pon, context: &mut TranslateContext) -> Result<Vec<MeshProcess>, PonTranslateErr> {
+    let steps = match node.as_typed(|&TypedPon { ref data, .. }| Ok(data.field("process").ok().cloned())) {
+        Ok(Some(Pon::Array(steps))) => steps,
+        Ok(Some(steps)) => return Err(PonTranslateErr::InvalidValue { value: format!("{:?}", steps) }),
+        _ => return Ok(vec![])
+    };
+    let mut process = vec![];
+    for step in steps {
+        if let Ok(name) = step.translate::<String>(context) {
+            process.push(match name.as_str() {
+                "smooth_normals" => MeshProcess::SmoothNormals,
+                "flat_normals" => MeshProcess::FlatNormals,
+                "tangents" => MeshProcess::Tangents,
+                "weld" => MeshProcess::Weld { epsilon: DEFAULT_WELD_EPSILON },
+                "flip_winding" => MeshProcess::FlipWinding,
+                _ => return Err(PonTranslateErr::InvalidValue { value: name.clone() })
+            });
+            continue;
+        }
+        process.push(try!(step.as_typed(|&TypedPon { ref type_name, ref data }| match type_name.as_str() {
+            "weld" => Ok(MeshProcess::Weld { epsilon: try!(data.field_as_or::<f32>("epsilon", DEFAULT_WELD_EPSILON, context)) }),
+            _ => Err(PonTranslateErr::UnrecognizedType(type_name.clone()))
+        })));
+    }
+    Ok(process)
+}
+
+pub fn pon_to_mesh(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<Box<LoadableMesh>, PonTranslateErr> {
+    let mesh = try!(pon_to_mesh_without_options(root_path, node, context));
+    let process = try!(pon_to_mesh_process(node, context));
+    let format = try!(pon_to_vertex_format(node, context));
+    let topology = match node.as_typed(|&TypedPon { ref data, .. }| Ok(data.field("topology").ok().cloned())) {
+        Ok(Some(topology)) => try!(topology.translate::<MeshTopology>(context)),
+        _ => MeshTopology::Triangles
+    };
+    if process.len() > 0 && topology != MeshTopology::Triangles {
+        return Err(PonTranslateErr::Generic(format!("Only triangle meshes can be processed, the topology is {:?}", topology)));
+    }
+    if process.len() == 0 && format.0.len() == 0 && topology == MeshTopology::Triangles {
+        return Ok(mesh);
+    }
+    Ok(Box::new(MeshWithOptions { mesh: mesh, process: process, format: format, topology: topology }))
+}
+
+fn pon_to_mesh_without_options(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<Box<LoadableMesh>, PonTranslateErr> {
     println!("Pon to mesh");
-    node.as_typed(|&TypedPon { type_name: ref type_name, ref data }| {
+    node.as_typed(|&TypedPon { type_name: ref type_name, ref data }| -> Result<Box<LoadableMesh>, PonTranslateErr> {
         match type_name.as_str() {
             "static_mesh" => {
                 let attribs = try!(data.field_as::<PonAutoVec<LocalAttributeSpec>>("layout", context)).0.into_iter().map(|x| x.0).collect();
@@ -56,45 +493,174 @@ pub fn pon_to_mesh(root_path: &Path, node: &Pon, context: &mut TranslateContext)
                 let vertices = try!(data.field_as::<Vec<f32>>("vertices", context));
                 let indices = try!(data.field_as::<Vec<i64>>("indices", context));
 
-                return Ok(Rc::new(Mesh {
+                return Ok(AsyncMesh::new(Box::new(StaticMeshBuilder {
                     layout: layout,
-                    vertex_data: vertices,
-                    element_data: indices.iter().map(|x| *x as u32).collect()
-                }));
+                    vertices: vertices,
+                    indices: indices
+                })));
             },
             "mesh_from_resource" => {
                 let resource_id = try!(data.translate::<String>(context));
-                return Ok(context.document.unwrap().resources.get(&resource_id).unwrap().downcast_ref::<Rc<Mesh>>().unwrap().clone());
+                let document = match context.document {
+                    Some(document) => document,
+                    None => return Err(PonTranslateErr::Generic("mesh_from_resource needs a document".to_string()))
+                };
+                return match document.resources.get(&resource_id) {
+                    Some(resource) => match resource.downcast_ref::<Rc<Mesh>>() {
+                        Some(mesh) => Ok(Box::new(ResourceMesh { mesh: mesh.clone() })),
+                        None => Err(PonTranslateErr::Generic(format!("Resource {} is not a mesh", resource_id)))
+                    },
+                    None => Err(PonTranslateErr::Generic(format!("No such resource: {}", resource_id)))
+                };
             },
-            "grid_mesh" => {
-                let mut grid = Grid::new();
-                let layout = match data.field_as::<PonAutoVec<LocalAttributeSpec>>("layout", context) {
-                    Ok(attrs) => {
-                        let attribs = attrs.0.into_iter().map(|x| x.0).collect();
-                        Layout::new(attribs)
+            "mesh_from_file" => {
+                // Either just a path, or { path: .., group: .. } to load a single group
+                let (filename, group) = match data.translate::<String>(context) {
+                    Ok(filename) => (filename, None),
+                    Err(_) => (try!(data.field_as::<String>("path", context)), data.field_as::<String>("group", context).ok())
+                };
+                return Ok(Box::new(MeshFromFile {
+                    path: root_path.join(Path::new(&filename)),
+                    group: group
+                }));
+            },
+            "gltf_mesh" => {
+                // { path: .., mesh: .. } loads a mesh as is, { path: .., node: .. } loads the mesh
+                // of a node with the node's transform applied
+                let path = root_path.join(Path::new(&try!(data.field_as::<String>("path", context))));
+                let source = match data.field("node") {
+                    Ok(_) => GltfMeshSource::Node(try!(pon_to_gltf_ref(data, "node", context))),
+                    Err(_) => GltfMeshSource::Mesh(try!(pon_to_gltf_ref(data, "mesh", context)))
+                };
+                return Ok(Box::new(GltfMesh {
+                    path: path,
+                    source: source,
+                    primitive: try!(data.field_as_or::<i64>("primitive", 0, context)) as usize
+                }));
+            },
+            "heightmap_mesh" => {
+                // Either the whole terrain, or with chunk: [x, y] one chunk_size by chunk_size
+                // quads part of it
+                let chunk = match data.field_as::<Vec<i64>>("chunk", context) {
+                    Ok(chunk) => {
+                        if chunk.len() != 2 || chunk.iter().any(|&c| c < 0 || c > u32::max_value() as i64) {
+                            return Err(PonTranslateErr::InvalidValue { value: format!("{:?}", chunk) });
+                        }
+                        Some(TerrainChunk {
+                            x: chunk[0] as u32,
+                            y: chunk[1] as u32,
+                            size: try!(count_field(data, "chunk_size", 64, 1, context))
+                        })
                     },
-                    Err(_) => Layout::position_texcoord_normal()
+                    Err(_) => None
                 };
-                grid.layout = layout;
+                return Ok(Box::new(HeightmapMesh {
+                    path: root_path.join(Path::new(&try!(data.field_as::<String>("path", context)))),
+                    options: TerrainOptions {
+                        layout: layout_or_default(data, context),
+                        horizontal_scale: try!(data.field_as_or::<f32>("horizontal_scale", 1.0, context)),
+                        vertical_scale: try!(data.field_as_or::<f32>("vertical_scale", 1.0, context)),
+                        chunk: chunk
+                    }
+                }));
+            },
+            "grid_mesh" => {
+                let mut grid = Grid::new();
+                grid.layout = layout_or_default(data, context);
                 grid.n_vertices_width = try!(data.field_as::<i64>("n_vertices_width", context)) as u32;
                 grid.n_vertices_height = try!(data.field_as::<i64>("n_vertices_height", context)) as u32;
 
-                return Ok(Rc::new(grid.into()));
+                return Ok(AsyncMesh::new(Box::new(grid)));
             },
             "box_mesh" => {
                 let mut box_mesh = Box3::new();
-                let layout = match data.field_as::<PonAutoVec<LocalAttributeSpec>>("layout", context) {
-                    Ok(attrs) => {
-                        let attribs = attrs.0.into_iter().map(|x| x.0).collect();
-                        Layout::new(attribs)
-                    },
-                    Err(_) => Layout::position_texcoord_normal()
-                };
-                box_mesh.layout = layout;
+                box_mesh.layout = layout_or_default(data, context);
                 box_mesh.position = try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context));
                 box_mesh.size = try!(data.field_as_or::<Vector3<f32>>("size", Vector3::one(), context));
 
-                return Ok(Rc::new(box_mesh.into()));
+                return Ok(AsyncMesh::new(Box::new(box_mesh)));
+            },
+            "plane_mesh" => {
+                return Ok(AsyncMesh::new(Box::new(Plane {
+                    layout: layout_or_default(data, context),
+                    position: try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context)),
+                    width: try!(data.field_as_or::<f32>("width", 1.0, context)),
+                    height: try!(data.field_as_or::<f32>("height", 1.0, context)),
+                    segments_width: try!(count_field(data, "segments_width", 1, 1, context)),
+                    segments_height: try!(count_field(data, "segments_height", 1, 1, context))
+                })));
+            },
+            "disc_mesh" => {
+                return Ok(AsyncMesh::new(Box::new(Disc {
+                    layout: layout_or_default(data, context),
+                    position: try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context)),
+                    radius: try!(data.field_as_or::<f32>("radius", 0.5, context)),
+                    segments: try!(count_field(data, "segments", 32, 3, context))
+                })));
+            },
+            "sphere_mesh" => {
+                let layout = layout_or_default(data, context);
+                let position = try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context));
+                let radius = try!(data.field_as_or::<f32>("radius", 0.5, context));
+                let kind = try!(data.field_as_or::<String>("kind", "uv".to_string(), context));
+                return match kind.as_str() {
+                    "uv" => Ok(AsyncMesh::new(Box::new(UvSphere {
+                        layout: layout,
+                        position: position,
+                        radius: radius,
+                        segments: try!(count_field(data, "segments", 32, 3, context)),
+                        rings: try!(count_field(data, "rings", 16, 2, context))
+                    }))),
+                    "ico" => Ok(AsyncMesh::new(Box::new(Icosphere {
+                        layout: layout,
+                        position: position,
+                        radius: radius,
+                        subdivisions: try!(count_field(data, "subdivisions", 2, 0, context))
+                    }))),
+                    _ => Err(PonTranslateErr::InvalidValue { value: kind.clone() })
+                };
+            },
+            "cylinder_mesh" => {
+                return Ok(AsyncMesh::new(Box::new(Cylinder {
+                    layout: layout_or_default(data, context),
+                    position: try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context)),
+                    radius: try!(data.field_as_or::<f32>("radius", 0.5, context)),
+                    height: try!(data.field_as_or::<f32>("height", 1.0, context)),
+                    segments: try!(count_field(data, "segments", 32, 3, context)),
+                    rings: try!(count_field(data, "rings", 1, 1, context)),
+                    caps: try!(data.field_as_or::<bool>("caps", true, context))
+                })));
+            },
+            "cone_mesh" => {
+                return Ok(AsyncMesh::new(Box::new(Cone {
+                    layout: layout_or_default(data, context),
+                    position: try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context)),
+                    radius: try!(data.field_as_or::<f32>("radius", 0.5, context)),
+                    height: try!(data.field_as_or::<f32>("height", 1.0, context)),
+                    segments: try!(count_field(data, "segments", 32, 3, context)),
+                    rings: try!(count_field(data, "rings", 1, 1, context)),
+                    cap: try!(data.field_as_or::<bool>("cap", true, context))
+                })));
+            },
+            "torus_mesh" => {
+                return Ok(AsyncMesh::new(Box::new(Torus {
+                    layout: layout_or_default(data, context),
+                    position: try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context)),
+                    radius: try!(data.field_as_or::<f32>("radius", 0.375, context)),
+                    tube_radius: try!(data.field_as_or::<f32>("tube_radius", 0.125, context)),
+                    segments: try!(count_field(data, "segments", 32, 3, context)),
+                    tube_segments: try!(count_field(data, "tube_segments", 16, 3, context))
+                })));
+            },
+            "capsule_mesh" => {
+                return Ok(AsyncMesh::new(Box::new(Capsule {
+                    layout: layout_or_default(data, context),
+                    position: try!(data.field_as_or::<Vector3<f32>>("position", Vector3::zero(), context)),
+                    radius: try!(data.field_as_or::<f32>("radius", 0.25, context)),
+                    height: try!(data.field_as_or::<f32>("height", 0.5, context)),
+                    segments: try!(count_field(data, "segments", 32, 3, context)),
+                    rings: try!(count_field(data, "rings", 8, 1, context))
+                })));
             },
             _ => Err(PonTranslateErr::UnrecognizedType(type_name.clone()))
         }
@@ -102,106 +668,539 @@ pub fn pon_to_mesh(root_path: &Path, node: &Pon, context: &mut TranslateContext)
 }
 
 pub trait LoadableTexture {
-    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Texture>;
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>>;
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![]
+    }
+    fn source_resource(&self) -> Option<String> {
+        None
+    }
+    fn dynamic_texture(&self) -> Option<DynamicTexture> {
+        None
+    }
 }
 struct StaticTexture {
     texture: Option<Texture>
 }
 impl LoadableTexture for StaticTexture {
-    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Texture> {
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
         let texture = mem::replace(&mut self.texture, None);
-        Promise::resolved(texture.unwrap())
+        Promise::resolved(Ok(texture.unwrap()))
+    }
+}
+struct AsyncTexture {
+    generator: Option<Box<TextureGenerator>>
+}
+impl AsyncTexture {
+    fn new(generator: Box<TextureGenerator>) -> Box<LoadableTexture> {
+        Box::new(AsyncTexture { generator: Some(generator) })
+    }
+}
+impl LoadableTexture for AsyncTexture {
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
+        let generator = mem::replace(&mut self.generator, None).unwrap();
+        async_runner.exec_async(move || generator.generate())
+    }
+}
+struct TextureFromResource {
+    resource_id: String,
+    texture: Option<Result<Texture, ResourceErr>>
+}
+impl LoadableTexture for TextureFromResource {
+    fn source_resource(&self) -> Option<String> {
+        Some(self.resource_id.clone())
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
+        Promise::resolved(mem::replace(&mut self.texture, None).unwrap())
+    }
+}
+
+struct DynamicTextureSource {
+    texture: DynamicTexture
+}
+impl LoadableTexture for DynamicTextureSource {
+    fn dynamic_texture(&self) -> Option<DynamicTexture> {
+        Some(self.texture.clone())
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
+        // The changed region is left for the other GL textures of the same dynamic texture; it is
+        // part of this image already, so uploading it again once this one loads is harmless
+        Promise::resolved(Ok(Texture::Image(self.texture.image())))
     }
 }
+
+fn texture_from_resource(document: &Document, resource_id: &str) -> Result<Texture, ResourceErr> {
+    Ok(match try!(resource_identity(document, resource_id)) {
+        ResourceIdentity::Image(image) => Texture::Image((*image).clone()),
+        ResourceIdentity::Texture(texture) => (*texture).clone()
+    })
+}
+
+/// A texture resource, which has to be shared through an `Rc` so that replacing it can be
+/// detected by pointer. Holding on to the `Rc` keeps the old value alive, so a new value can never
+/// end up at the same address.
+#[derive(Clone)]
+pub enum ResourceIdentity {
+    Image(Rc<RgbaImage>),
+    Texture(Rc<Texture>)
+}
+
+impl ResourceIdentity {
+    pub fn matches(&self, document: &Document, resource_id: &str) -> bool {
+        match (self, resource_identity(document, resource_id)) {
+            (&ResourceIdentity::Image(ref a), Ok(ResourceIdentity::Image(ref b))) => &**a as *const RgbaImage == &**b as *const RgbaImage,
+            (&ResourceIdentity::Texture(ref a), Ok(ResourceIdentity::Texture(ref b))) => &**a as *const Texture == &**b as *const Texture,
+            _ => false
+        }
+    }
+}
+
+pub fn resource_identity(document: &Document, resource_id: &str) -> Result<ResourceIdentity, ResourceErr> {
+    let resource = match document.resources.get(resource_id) {
+        Some(resource) => resource,
+        None => return Err(ResourceErr::Translate(format!("No such resource: {}", resource_id)))
+    };
+    if let Some(image) = resource.downcast_ref::<Rc<RgbaImage>>() {
+        return Ok(ResourceIdentity::Image(image.clone()));
+    }
+    if let Some(texture) = resource.downcast_ref::<Rc<Texture>>() {
+        return Ok(ResourceIdentity::Texture(texture.clone()));
+    }
+    if resource.is::<RgbaImage>() || resource.is::<Texture>() {
+        return Err(ResourceErr::Translate(format!("Resource {} is stored by value, texture resources have to be an Rc<RgbaImage> or Rc<Texture>", resource_id)));
+    }
+    Err(ResourceErr::Translate(format!("Resource {} is not a texture", resource_id)))
+}
+
 struct TextureFromFile {
     path: PathBuf
 }
 impl LoadableTexture for TextureFromFile {
-    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Texture> {
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
+        let path = self.path.clone();
+        async_runner.exec_async(move || texture_from_file(&path))
+    }
+}
+
+struct GltfTexture {
+    path: PathBuf,
+    material: GltfRef
+}
+impl LoadableTexture for GltfTexture {
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
         let path = self.path.clone();
+        let material = self.material.clone();
+        async_runner.exec_async(move || gltf::load_base_color_texture(&path, &material).map(|image| Texture::Image(image)))
+    }
+}
+
+pub fn texture_from_file(path: &Path) -> Result<Texture, ResourceErr> {
+    println!("Loading image {:?}", path);
+    if path.extension().and_then(|ext| ext.to_str()) == Some("dhm") {
+        let mut f = try!(File::open(&path).map_err(|err| ResourceErr::io(path, err)));
+        let mut data = vec![];
+        try!(f.read_to_end(&mut data).map_err(|err| ResourceErr::io(path, err)));
+        read_dhm(&data).map_err(|err| ResourceErr::Io { path: path.to_path_buf(), message: err })
+    } else {
+        let img = image::open(&path);
+        println!("Image loaded!");
+        match img {
+            Ok(img) => Ok(Texture::Image(img.to_rgba())),
+            Err(err) => Err(ResourceErr::io(path, err))
+        }
+    }
+}
+
+struct CubemapFromFiles {
+    paths: Vec<PathBuf>
+}
+impl LoadableTexture for CubemapFromFiles {
+    fn source_files(&self) -> Vec<PathBuf> {
+        self.paths.clone()
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
+        let paths = self.paths.clone();
         async_runner.exec_async(move || {
-            println!("Loading image {:?}", path);
-            if path.extension().unwrap().to_str().unwrap() == "dhm" {
-                let mut f = File::open(&path).unwrap();
-                let mut data = vec![];
-                f.read_to_end(&mut data);
-                let mut rdr = Cursor::new(data);
-                let width = rdr.read_i32::<LittleEndian>().unwrap() as u32;
-                let height = rdr.read_i32::<LittleEndian>().unwrap() as u32;
-                println!("SIZE {}, {}", width, height);
-                let mut data = vec![];
-                for y in 0..height {
-                    for x in 0..width {
-                        data.push(rdr.read_f32::<LittleEndian>().unwrap());
-                    }
-                }
-                return Texture::Floats { width: width, height: height, data: data }
-            } else {
-                let img = image::open(&path);
-                println!("Image loaded!");
-                return match img {
-                    Ok(img) => Texture::Image(img.to_rgba()),
-                    Err(err) => panic!("Failed to load image: {}: {:?}", path.to_str().unwrap(), err)
-                };
+            let mut faces = vec![];
+            for path in &paths {
+                faces.push(try!(image::open(path).map_err(|err| ResourceErr::io(path, err))).to_rgba());
+            }
+            let size = faces[0].width();
+            if faces.iter().any(|face| face.width() != size || face.height() != size) {
+                return Err(ResourceErr::Io { path: paths[0].clone(), message: "Cubemap faces must be square and of the same size".to_string() });
             }
+            Ok(Texture::Cubemap(faces))
+        })
+    }
+}
+struct CubemapFromCross {
+    path: PathBuf
+}
+impl LoadableTexture for CubemapFromCross {
+    fn source_files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+    fn load(&mut self, async_runner: &mut AsyncRunner) -> Promise<Result<Texture, ResourceErr>> {
+        let path = self.path.clone();
+        async_runner.exec_async(move || {
+            let image = try!(image::open(&path).map_err(|err| ResourceErr::io(&path, err))).to_rgba();
+            split_cross(&image).map(|faces| Texture::Cubemap(faces))
+                .map_err(|err| ResourceErr::Io { path: path.clone(), message: err })
         })
     }
 }
 
+fn copy_face(image: &RgbaImage, column: u32, row: u32, size: u32, rotate: bool) -> RgbaImage {
+    let mut face = RgbaImage::new(size, size);
+    for y in 0..size {
+        for x in 0..size {
+            let (sx, sy) = if rotate { (size - 1 - x, size - 1 - y) } else { (x, y) };
+            face.put_pixel(x, y, *image.get_pixel(column * size + sx, row * size + sy));
+        }
+    }
+    face
+}
+
+/// Splits a cross layout image into cubemap faces. A horizontal cross is 4x3 faces:
+///
+/// ```text
+///     +Y
+/// -X  +Z  +X  -Z
+///     -Y
+/// ```
+///
+/// A vertical cross is 3x4 faces with -Z below -Y, upside down.
+fn split_cross(image: &RgbaImage) -> Result<Vec<RgbaImage>, String> {
+    let (width, height) = (image.width(), image.height());
+    if width * 3 == height * 4 && width % 4 == 0 {
+        let size = width / 4;
+        Ok(vec![
+            copy_face(image, 2, 1, size, false),
+            copy_face(image, 0, 1, size, false),
+            copy_face(image, 1, 0, size, false),
+            copy_face(image, 1, 2, size, false),
+            copy_face(image, 1, 1, size, false),
+            copy_face(image, 3, 1, size, false)
+        ])
+    } else if width * 4 == height * 3 && width % 3 == 0 {
+        let size = width / 3;
+        Ok(vec![
+            copy_face(image, 2, 1, size, false),
+            copy_face(image, 0, 1, size, false),
+            copy_face(image, 1, 0, size, false),
+            copy_face(image, 1, 2, size, false),
+            copy_face(image, 1, 1, size, false),
+            copy_face(image, 1, 3, size, true)
+        ])
+    } else {
+        Err(format!("{}x{} is not a 4x3 or 3x4 cross layout", width, height))
+    }
+}
+
+const DHM_MAGIC: &'static [u8; 4] = b"DHMX";
+const DHM_VERSION: u32 = 1;
+
+/// Reads a `.dhm` float texture. The original format is an i32 width and height followed by one
+/// f32 per texel. The extended format starts with `b"DHMX"`, then u32 version, width, height,
+/// channels and component format (0 for f32, 1 for f16), followed by the texel data.
+fn read_dhm(data: &[u8]) -> Result<Texture, String> {
+    let mut rdr = Cursor::new(data);
+    let (width, height, channels, half) = if data.len() >= 4 && &data[0..4] == DHM_MAGIC {
+        rdr.set_position(4);
+        let version = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+        if version != DHM_VERSION {
+            return Err(format!("unsupported dhm version {}", version));
+        }
+        let width = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+        let height = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+        let channels = try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err)));
+        let half = match try!(rdr.read_u32::<LittleEndian>().map_err(|err| format!("{:?}", err))) {
+            0 => false,
+            1 => true,
+            format => return Err(format!("unknown dhm component format {}", format))
+        };
+        if channels < 1 || channels > 4 {
+            return Err(format!("invalid channel count {}", channels));
+        }
+        (width, height, channels, half)
+    } else {
+        let width = try!(rdr.read_i32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as u32;
+        let height = try!(rdr.read_i32::<LittleEndian>().map_err(|err| format!("{:?}", err))) as u32;
+        (width, height, 1, false)
+    };
+    let bytes_per_value = if half { 2 } else { 4 };
+    let n = match texel_count(width, height, channels) {
+        Some(n) if n.checked_mul(bytes_per_value).map(|bytes| bytes <= data.len() - rdr.position() as usize).unwrap_or(false) => n,
+        _ => return Err(format!("a {}x{} heightmap with {} channels does not fit in {} bytes", width, height, channels, data.len()))
+    };
+    let mut values = Vec::with_capacity(n);
+    for _ in 0..n {
+        let value = if half {
+            rdr.read_u16::<LittleEndian>().map(half_to_f32)
+        } else {
+            rdr.read_f32::<LittleEndian>()
+        };
+        values.push(try!(value.map_err(|err| format!("{:?}", err))));
+    }
+    Ok(Texture::Floats { width: width, height: height, channels: channels, half: half, data: values })
+}
+
+fn texel_count(width: u32, height: u32, channels: u32) -> Option<usize> {
+    (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(channels as usize))
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * (2.0f32).powi(-24),
+        31 => if mantissa == 0.0 { sign * ::std::f32::INFINITY } else { ::std::f32::NAN },
+        _ => sign * (1.0 + mantissa / 1024.0) * (2.0f32).powi(exponent - 15)
+    }
+}
+
+pub fn checkerboard_texture() -> Texture {
+    let size = 64;
+    let mut pixels = vec![];
+    for y in 0..size {
+        for x in 0..size {
+            if (x / 8 + y / 8) % 2 == 0 {
+                pixels.extend([255, 0, 255, 255].iter().cloned());
+            } else {
+                pixels.extend([0, 0, 0, 255].iter().cloned());
+            }
+        }
+    }
+    Texture::Image(RgbaImage::from_raw(size, size, pixels).unwrap())
+}
+
+fn pon_to_color(data: &Pon, field: &str, default: Color, context: &mut TranslateContext) -> Result<Color, PonTranslateErr> {
+    if data.field(field).is_err() {
+        return Ok(default);
+    }
+    let values = try!(data.field_as::<Vec<i64>>(field, context));
+    match values.len() {
+        3 => Ok([values[0] as u8, values[1] as u8, values[2] as u8, 255]),
+        4 => Ok([values[0] as u8, values[1] as u8, values[2] as u8, values[3] as u8]),
+        _ => Err(PonTranslateErr::Generic(format!("Expected 3 or 4 color components in {}, found {}", field, values.len())))
+    }
+}
+
+pub fn pon_to_texture_sampler(node: &Pon, context: &mut TranslateContext) -> Result<TextureSampler, PonTranslateErr> {
+    node.as_typed(|&TypedPon { ref type_name, ref data }| {
+        let default = TextureSampler::default();
+        match type_name.as_str() {
+            "sampled_texture" => Ok(TextureSampler {
+                min_filter: try!(data.field_as_or::<TextureFilter>("min_filter", default.min_filter, context)),
+                mag_filter: try!(data.field_as_or::<TextureFilter>("mag_filter", default.mag_filter, context)),
+                mipmaps: try!(data.field_as_or::<bool>("mipmaps", default.mipmaps, context)),
+                wrap_s: try!(data.field_as_or::<TextureWrap>("wrap_s", default.wrap_s, context)),
+                wrap_t: try!(data.field_as_or::<TextureWrap>("wrap_t", default.wrap_t, context)),
+                anisotropy: try!(data.field_as_or::<f32>("anisotropy", default.anisotropy, context))
+            }),
+            _ => Ok(default)
+        }
+    })
+}
+
 pub fn pon_to_texture(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<Box<LoadableTexture>, PonTranslateErr> {
     println!("Pon to texture");
     node.as_typed(|&TypedPon { ref type_name, ref data }| -> Result<Box<LoadableTexture>, PonTranslateErr> {
         match type_name.as_str() {
+            "sampled_texture" => pon_to_texture(root_path, try!(data.field("texture")), context),
             "static_texture" => {
                 let pixel_data = try!(data.field_as::<Vec<i64>>("pixels", context));
                 let pixel_data: Vec<u8> = pixel_data.iter().map(|x| *x as u8).collect();
                 let width = try!(data.field_as::<i64>("width", context)) as u32;
                 let height = try!(data.field_as::<i64>("height", context)) as u32;
-                if width * height * 4 != pixel_data.len() as u32 {
-                    return Err(PonTranslateErr::Generic(format!("Expected {} pixels, found {}", width * height * 4, pixel_data.len())));
+                if texel_count(width, height, 4) != Some(pixel_data.len()) {
+                    return Err(PonTranslateErr::Generic(format!("Expected {}x{}x4 pixel values, found {}", width, height, pixel_data.len())));
                 }
                 return match RgbaImage::from_raw(width, height, pixel_data) {
                     Some(image) => Ok(Box::new(StaticTexture { texture: Some(Texture::Image(image)) })),
                     None => Err(PonTranslateErr::Generic("Failed to create image in static_texture".to_string()))
                 }
             },
+            "static_float_texture" => {
+                let width = try!(data.field_as::<i64>("width", context)) as u32;
+                let height = try!(data.field_as::<i64>("height", context)) as u32;
+                let channels = try!(data.field_as_or::<i64>("channels", 1, context)) as u32;
+                let half = try!(data.field_as_or::<bool>("half", false, context));
+                let values = try!(data.field_as::<Vec<f32>>("data", context));
+                if channels < 1 || channels > 4 {
+                    return Err(PonTranslateErr::Generic(format!("channels must be between 1 and 4, found {}", channels)));
+                }
+                if texel_count(width, height, channels) != Some(values.len()) {
+                    return Err(PonTranslateErr::Generic(format!("Expected {}x{}x{} values, found {}", width, height, channels, values.len())));
+                }
+                Ok(Box::new(StaticTexture { texture: Some(Texture::Floats { width: width, height: height, channels: channels, half: half, data: values }) }))
+            },
+            "solid_color_texture" => {
+                Ok(AsyncTexture::new(Box::new(SolidColor {
+                    width: try!(count_field(data, "width", 1, 1, context)),
+                    height: try!(count_field(data, "height", 1, 1, context)),
+                    color: try!(pon_to_color(data, "color", [255, 255, 255, 255], context))
+                })))
+            },
+            "checker_texture" => {
+                Ok(AsyncTexture::new(Box::new(Checker {
+                    width: try!(count_field(data, "width", 64, 1, context)),
+                    height: try!(count_field(data, "height", 64, 1, context)),
+                    size: try!(count_field(data, "size", 8, 1, context)),
+                    color_a: try!(pon_to_color(data, "color_a", [255, 255, 255, 255], context)),
+                    color_b: try!(pon_to_color(data, "color_b", [0, 0, 0, 255], context))
+                })))
+            },
+            "gradient_texture" => {
+                let direction = try!(data.field_as_or::<String>("direction", "horizontal".to_string(), context));
+                let direction = match direction.as_str() {
+                    "horizontal" => GradientDirection::Horizontal,
+                    "vertical" => GradientDirection::Vertical,
+                    "radial" => GradientDirection::Radial,
+                    _ => return Err(PonTranslateErr::InvalidValue { value: direction.clone() })
+                };
+                Ok(AsyncTexture::new(Box::new(Gradient {
+                    width: try!(count_field(data, "width", 64, 1, context)),
+                    height: try!(count_field(data, "height", 64, 1, context)),
+                    from: try!(pon_to_color(data, "from", [0, 0, 0, 255], context)),
+                    to: try!(pon_to_color(data, "to", [255, 255, 255, 255], context)),
+                    direction: direction
+                })))
+            },
+            "noise_texture" => {
+                let kind = try!(data.field_as_or::<String>("kind", "perlin".to_string(), context));
+                let kind = match kind.as_str() {
+                    "value" => NoiseKind::Value,
+                    "perlin" => NoiseKind::Perlin,
+                    _ => return Err(PonTranslateErr::InvalidValue { value: kind.clone() })
+                };
+                Ok(AsyncTexture::new(Box::new(Noise {
+                    width: try!(count_field(data, "width", 256, 1, context)),
+                    height: try!(count_field(data, "height", 256, 1, context)),
+                    kind: kind,
+                    seed: try!(data.field_as_or::<i64>("seed", 0, context)) as u32,
+                    octaves: try!(count_field(data, "octaves", 4, 1, context)),
+                    scale: try!(data.field_as_or::<f32>("scale", 4.0, context)),
+                    from: try!(pon_to_color(data, "from", [0, 0, 0, 255], context)),
+                    to: try!(pon_to_color(data, "to", [255, 255, 255, 255], context))
+                })))
+            },
+            "cubemap_texture" => {
+                // Either a single cross layout image, or one image per face
+                if let Ok(filename) = data.translate::<String>(context) {
+                    return Ok(Box::new(CubemapFromCross { path: root_path.join(Path::new(&filename)) }));
+                }
+                let mut paths = vec![];
+                for face in &["positive_x", "negative_x", "positive_y", "negative_y", "positive_z", "negative_z"] {
+                    let filename = try!(data.field_as::<String>(face, context));
+                    paths.push(root_path.join(Path::new(&filename)));
+                }
+                Ok(Box::new(CubemapFromFiles { paths: paths }))
+            },
+            "texture_from_resource" => {
+                let resource_id = try!(data.translate::<String>(context));
+                let document = match context.document {
+                    Some(document) => document,
+                    None => return Err(PonTranslateErr::Generic("texture_from_resource needs a document".to_string()))
+                };
+                // A missing resource is a load error rather than a translation error, so that the
+                // resource is still watched and the texture loads once it is added
+                Ok(Box::new(TextureFromResource {
+                    texture: Some(texture_from_resource(document, &resource_id)),
+                    resource_id: resource_id
+                }))
+            },
+            "dynamic_texture" => {
+                let resource_id = try!(data.translate::<String>(context));
+                let document = match context.document {
+                    Some(document) => document,
+                    None => return Err(PonTranslateErr::Generic("dynamic_texture needs a document".to_string()))
+                };
+                match document.resources.get(&resource_id) {
+                    Some(resource) => match resource.downcast_ref::<DynamicTexture>() {
+                        Some(texture) => Ok(Box::new(DynamicTextureSource { texture: texture.clone() })),
+                        None => Err(PonTranslateErr::Generic(format!("Resource {} is not a dynamic texture", resource_id)))
+                    },
+                    None => Err(PonTranslateErr::Generic(format!("No such resource: {}", resource_id)))
+                }
+            },
             "texture_from_file" => {
                 let filename = try!(data.translate::<String>(context));
                 let path_buff = root_path.join(Path::new(&filename));
                 Ok(Box::new(TextureFromFile { path: path_buff }))
             },
+            "gltf_texture" => {
+                let path = root_path.join(Path::new(&try!(data.field_as::<String>("path", context))));
+                Ok(Box::new(GltfTexture {
+                    path: path,
+                    material: try!(pon_to_gltf_ref(data, "material", context))
+                }))
+            },
             _ => Err(PonTranslateErr::UnrecognizedType(type_name.clone()))
         }
     })
 }
 
-pub fn pon_to_shader(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<ShaderSource, PonTranslateErr> {
+pub enum ShaderStageSource {
+    File(PathBuf),
+    Static(String)
+}
+
+impl ShaderStageSource {
+    fn load(&self) -> Result<String, ResourceErr> {
+        match self {
+            &ShaderStageSource::File(ref path) => string_from_file(path),
+            &ShaderStageSource::Static(ref source) => Ok(source.clone())
+        }
+    }
+}
+
+pub struct LoadableShader {
+    pub vertex: ShaderStageSource,
+    pub fragment: ShaderStageSource,
+    pub debug_source_name: String
+}
+
+impl LoadableShader {
+    pub fn source_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for stage in &[&self.vertex, &self.fragment] {
+            if let &&ShaderStageSource::File(ref path) = stage {
+                files.push(path.clone());
+            }
+        }
+        files
+    }
+    pub fn load(self, async_runner: &mut AsyncRunner) -> Promise<Result<ShaderSource, ResourceErr>> {
+        async_runner.exec_async(move || {
+            let source_files = self.source_files();
+            Ok(ShaderSource {
+                vertex_src: try!(self.vertex.load()),
+                vertex_debug_source_name: self.debug_source_name.clone(),
+                fragment_src: try!(self.fragment.load()),
+                fragment_debug_source_name: self.debug_source_name,
+                source_files: source_files
+            })
+        })
+    }
+}
+
+pub fn pon_to_shader(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<LoadableShader, PonTranslateErr> {
     println!("Pon to shader");
     node.as_typed(|&TypedPon { ref type_name, ref data }| {
         match type_name.as_str() {
             "shader_program" => {
-                let vertex = (try!(data.field("vertex"))).as_typed(|&TypedPon { ref type_name, ref data }| {
-                    let string_arg = data.translate::<String>(context).unwrap();
-                    match type_name.as_str() {
-                        "shader_from_file" => Ok(string_from_file(&root_path.join(Path::new(&string_arg)))),
-                        "static_shader" => Ok(string_arg),
-                        _ => return Err(PonTranslateErr::UnrecognizedType(type_name.to_string()))
-                    }
-                });
-                let fragment = (try!(data.field("fragment"))).as_typed(|&TypedPon { ref type_name, ref data }| {
-                    let string_arg = data.translate::<String>(context).unwrap();
-                    match type_name.as_str() {
-                        "shader_from_file" => Ok(string_from_file(&root_path.join(Path::new(&string_arg)))),
-                        "static_shader" => Ok(string_arg),
-                        _ => return Err(PonTranslateErr::UnrecognizedType(type_name.to_string()))
-                    }
-                });
-
-                return Ok(ShaderSource {
-                    vertex_src: try!(vertex),
-                    vertex_debug_source_name: data.to_string(),
-                    fragment_src: try!(fragment),
-                    fragment_debug_source_name: data.to_string()
+                return Ok(LoadableShader {
+                    vertex: try!(pon_to_shader_stage(root_path, try!(data.field("vertex")), context)),
+                    fragment: try!(pon_to_shader_stage(root_path, try!(data.field("fragment")), context)),
+                    debug_source_name: data.to_string()
                 })
             },
             _ => Err(PonTranslateErr::UnrecognizedType(type_name.clone()))
@@ -209,14 +1208,25 @@ pub fn pon_to_shader(root_path: &Path, node: &Pon, context: &mut TranslateContex
     })
 }
 
-fn string_from_file(path: &Path) -> String {
+fn pon_to_shader_stage(root_path: &Path, node: &Pon, context: &mut TranslateContext) -> Result<ShaderStageSource, PonTranslateErr> {
+    node.as_typed(|&TypedPon { ref type_name, ref data }| {
+        let string_arg = try!(data.translate::<String>(context));
+        match type_name.as_str() {
+            "shader_from_file" => Ok(ShaderStageSource::File(root_path.join(Path::new(&string_arg)))),
+            "static_shader" => Ok(ShaderStageSource::Static(string_arg)),
+            _ => return Err(PonTranslateErr::UnrecognizedType(type_name.to_string()))
+        }
+    })
+}
+
+fn string_from_file(path: &Path) -> Result<String, ResourceErr> {
     let mut file = match File::open(&path) {
-        Err(why) => panic!("couldn't open {:?}: {}", path, Error::description(&why)),
+        Err(why) => return Err(ResourceErr::Io { path: path.to_path_buf(), message: format!("couldn't open: {}", Error::description(&why)) }),
         Ok(file) => file,
     };
     let mut content = String::new();
     match file.read_to_string(&mut content) {
-        Ok(_) => content,
-        Err(err) => panic!("Failed to read file {}", Error::description(&err))
+        Ok(_) => Ok(content),
+        Err(err) => Err(ResourceErr::Io { path: path.to_path_buf(), message: format!("Failed to read file: {}", Error::description(&err)) })
     }
 }
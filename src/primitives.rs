@@ -0,0 +1,390 @@
+//! Procedural mesh primitives. Attributes other than `position`, `texcoord` and `normal` are left at
+//! zero.
+
+use mesh::*;
+use pon_to_resource::{MeshBuilder, ResourceErr};
+
+use cgmath::Vector3;
+use std::cmp;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+struct Geometry {
+    positions: Vec<[f32; 3]>,
+    texcoords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>
+}
+
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: [f32; 2],
+    v: f32
+}
+
+impl Geometry {
+    fn new() -> Geometry {
+        Geometry { positions: vec![], texcoords: vec![], normals: vec![], indices: vec![] }
+    }
+    fn add_vertex(&mut self, position: [f32; 3], texcoord: [f32; 2], normal: [f32; 3]) -> u32 {
+        self.positions.push(position);
+        self.texcoords.push(texcoord);
+        self.normals.push(normal);
+        (self.positions.len() - 1) as u32
+    }
+    /// The vertices are expected to be added row by row right after this call.
+    fn add_grid_indices(&mut self, first: u32, columns: u32, rows: u32) {
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * (columns + 1) + column;
+                let b = a + 1;
+                let c = b + columns + 1;
+                let d = a + columns + 1;
+                self.indices.extend([a, b, c, a, c, d].iter().cloned());
+            }
+        }
+    }
+    /// The seam is duplicated so that texture coordinates wrap around once.
+    fn lathe(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let first = self.positions.len() as u32;
+        for point in profile {
+            for segment in 0..(segments + 1) {
+                let u = segment as f32 / segments as f32;
+                let (sin, cos) = (u * 2.0 * PI).sin_cos();
+                self.add_vertex(
+                    [point.radius * sin, point.y, point.radius * cos],
+                    [u, point.v],
+                    [point.normal[0] * sin, point.normal[1], point.normal[0] * cos]);
+            }
+        }
+        self.add_grid_indices(first, segments, profile.len() as u32 - 1);
+    }
+    fn cap(&mut self, y: f32, radius: f32, segments: u32, up: bool) {
+        let normal = if up { 1.0 } else { -1.0 };
+        let center = self.add_vertex([0.0, y, 0.0], [0.5, 0.5], [0.0, normal, 0.0]);
+        for segment in 0..(segments + 1) {
+            let (sin, cos) = (segment as f32 / segments as f32 * 2.0 * PI).sin_cos();
+            self.add_vertex([radius * sin, y, radius * cos], [0.5 + 0.5 * sin, 0.5 - 0.5 * normal * cos], [0.0, normal, 0.0]);
+        }
+        for segment in 0..segments {
+            let (a, b) = (center + 1 + segment, center + 2 + segment);
+            if up {
+                self.indices.extend([center, a, b].iter().cloned());
+            } else {
+                self.indices.extend([center, b, a].iter().cloned());
+            }
+        }
+    }
+    fn into_mesh(self, layout: Layout, position: Vector3<f32>) -> Mesh {
+        let mut vertex_data = vec![0.0; self.positions.len() * layout.stride];
+        for i in 0..self.positions.len() {
+            let p = self.positions[i];
+            let translated = [p[0] + position.x, p[1] + position.y, p[2] + position.z];
+            for attribute in &layout.attributes {
+                let values: &[f32] = match attribute.name.as_str() {
+                    "position" => &translated,
+                    "texcoord" => &self.texcoords[i],
+                    "normal" => &self.normals[i],
+                    _ => continue
+                };
+                let start = i * layout.stride + attribute.offset;
+                for j in 0..cmp::min(attribute.size, values.len()) {
+                    vertex_data[start + j] = values[j];
+                }
+            }
+        }
+        Mesh {
+            layout: layout,
+            vertex_data: vertex_data,
+            element_data: self.indices
+        }
+    }
+}
+
+fn frustum_profile(bottom_radius: f32, top_radius: f32, height: f32, rings: u32) -> Vec<ProfilePoint> {
+    let slope = bottom_radius - top_radius;
+    let length = (height * height + slope * slope).sqrt();
+    (0..(rings + 1)).map(|ring| {
+        let t = ring as f32 / rings as f32;
+        ProfilePoint {
+            radius: bottom_radius + (top_radius - bottom_radius) * t,
+            y: height * (t - 0.5),
+            normal: [height / length, slope / length],
+            v: t
+        }
+    }).collect()
+}
+
+fn arc_profile(radius: f32, y: f32, from: f32, to: f32, rings: u32, v_from: f32, v_to: f32) -> Vec<ProfilePoint> {
+    (0..(rings + 1)).map(|ring| {
+        let t = ring as f32 / rings as f32;
+        let (sin, cos) = (from + (to - from) * t).sin_cos();
+        ProfilePoint {
+            radius: radius * cos,
+            y: y + radius * sin,
+            normal: [cos, sin],
+            v: v_from + (v_to - v_from) * t
+        }
+    }).collect()
+}
+
+pub struct Plane {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub width: f32,
+    pub height: f32,
+    pub segments_width: u32,
+    pub segments_height: u32
+}
+
+impl Plane {
+    fn geometry(&self) -> Geometry {
+        let (columns, rows) = (self.segments_width, self.segments_height);
+        let mut geometry = Geometry::new();
+        for row in 0..(rows + 1) {
+            for column in 0..(columns + 1) {
+                let (u, v) = (column as f32 / columns as f32, row as f32 / rows as f32);
+                geometry.add_vertex([self.width * (u - 0.5), self.height * (v - 0.5), 0.0], [u, v], [0.0, 0.0, 1.0]);
+            }
+        }
+        geometry.add_grid_indices(0, columns, rows);
+        geometry
+    }
+}
+
+pub struct Disc {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub segments: u32
+}
+
+impl Disc {
+    fn geometry(&self) -> Geometry {
+        let segments = self.segments;
+        let mut geometry = Geometry::new();
+        let center = geometry.add_vertex([0.0, 0.0, 0.0], [0.5, 0.5], [0.0, 0.0, 1.0]);
+        for segment in 0..(segments + 1) {
+            let (sin, cos) = (segment as f32 / segments as f32 * 2.0 * PI).sin_cos();
+            geometry.add_vertex([self.radius * cos, self.radius * sin, 0.0], [0.5 + 0.5 * cos, 0.5 + 0.5 * sin], [0.0, 0.0, 1.0]);
+        }
+        for segment in 0..segments {
+            geometry.indices.extend([center, center + 1 + segment, center + 2 + segment].iter().cloned());
+        }
+        geometry
+    }
+}
+
+pub struct UvSphere {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub segments: u32,
+    pub rings: u32
+}
+
+impl UvSphere {
+    fn geometry(&self) -> Geometry {
+        let mut geometry = Geometry::new();
+        let profile = arc_profile(self.radius, 0.0, -PI / 2.0, PI / 2.0, self.rings, 0.0, 1.0);
+        geometry.lathe(&profile, self.segments);
+        geometry
+    }
+}
+
+pub struct Icosphere {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub subdivisions: u32
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+impl Icosphere {
+    fn geometry(&self) -> Geometry {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let mut points: Vec<[f32; 3]> = [
+            [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+            [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+            [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0]
+        ].iter().map(|p| normalize(*p)).collect();
+        let mut faces: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]
+        ];
+        for _ in 0..self.subdivisions {
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32, points: &mut Vec<[f32; 3]>| {
+                let key = (cmp::min(a, b), cmp::max(a, b));
+                *midpoints.entry(key).or_insert_with(|| {
+                    let (pa, pb) = (points[a as usize], points[b as usize]);
+                    points.push(normalize([pa[0] + pb[0], pa[1] + pb[1], pa[2] + pb[2]]));
+                    (points.len() - 1) as u32
+                })
+            };
+            let mut subdivided = Vec::with_capacity(faces.len() * 4);
+            for face in &faces {
+                let ab = midpoint(face[0], face[1], &mut points);
+                let bc = midpoint(face[1], face[2], &mut points);
+                let ca = midpoint(face[2], face[0], &mut points);
+                subdivided.push([face[0], ab, ca]);
+                subdivided.push([face[1], bc, ab]);
+                subdivided.push([face[2], ca, bc]);
+                subdivided.push([ab, bc, ca]);
+            }
+            faces = subdivided;
+        }
+        let mut geometry = Geometry::new();
+        for p in &points {
+            let texcoord = [0.5 + p[0].atan2(p[2]) / (2.0 * PI), 0.5 + p[1].asin() / PI];
+            geometry.add_vertex([p[0] * self.radius, p[1] * self.radius, p[2] * self.radius], texcoord, *p);
+        }
+        for face in &faces {
+            geometry.indices.extend(face.iter().cloned());
+        }
+        geometry
+    }
+}
+
+pub struct Cylinder {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    pub rings: u32,
+    pub caps: bool
+}
+
+impl Cylinder {
+    fn geometry(&self) -> Geometry {
+        let mut geometry = Geometry::new();
+        geometry.lathe(&frustum_profile(self.radius, self.radius, self.height, self.rings), self.segments);
+        if self.caps {
+            geometry.cap(-self.height / 2.0, self.radius, self.segments, false);
+            geometry.cap(self.height / 2.0, self.radius, self.segments, true);
+        }
+        geometry
+    }
+}
+
+pub struct Cone {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    pub rings: u32,
+    pub cap: bool
+}
+
+impl Cone {
+    fn geometry(&self) -> Geometry {
+        let mut geometry = Geometry::new();
+        geometry.lathe(&frustum_profile(self.radius, 0.0, self.height, self.rings), self.segments);
+        if self.cap {
+            geometry.cap(-self.height / 2.0, self.radius, self.segments, false);
+        }
+        geometry
+    }
+}
+
+pub struct Torus {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub tube_radius: f32,
+    pub segments: u32,
+    pub tube_segments: u32
+}
+
+impl Torus {
+    fn geometry(&self) -> Geometry {
+        let tube_segments = self.tube_segments;
+        let profile: Vec<ProfilePoint> = (0..(tube_segments + 1)).map(|segment| {
+            let t = segment as f32 / tube_segments as f32;
+            let (sin, cos) = (-PI + t * 2.0 * PI).sin_cos();
+            ProfilePoint {
+                radius: self.radius + self.tube_radius * cos,
+                y: self.tube_radius * sin,
+                normal: [cos, sin],
+                v: t
+            }
+        }).collect();
+        let mut geometry = Geometry::new();
+        geometry.lathe(&profile, self.segments);
+        geometry
+    }
+}
+
+/// `height` is the length of the cylinder part.
+pub struct Capsule {
+    pub layout: Layout,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    pub rings: u32
+}
+
+impl Capsule {
+    fn geometry(&self) -> Geometry {
+        let rings = self.rings;
+        // Texture coordinates follow the length of the profile
+        let length = PI * self.radius + self.height;
+        let v_equator = PI * self.radius / 2.0 / length;
+        let mut profile = arc_profile(self.radius, -self.height / 2.0, -PI / 2.0, 0.0, rings, 0.0, v_equator);
+        profile.extend(arc_profile(self.radius, self.height / 2.0, 0.0, PI / 2.0, rings, 1.0 - v_equator, 1.0));
+        let mut geometry = Geometry::new();
+        geometry.lathe(&profile, self.segments);
+        geometry
+    }
+}
+
+impl MeshBuilder for Plane {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for Disc {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for UvSphere {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for Icosphere {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for Cylinder {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for Cone {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for Torus {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
+impl MeshBuilder for Capsule {
+    fn build(self: Box<Self>) -> Result<Mesh, ResourceErr> {
+        Ok(self.geometry().into_mesh(self.layout, self.position))
+    }
+}
@@ -0,0 +1,172 @@
+use pon_to_resource::{Texture, ResourceErr};
+
+use image::{RgbaImage, Rgba};
+
+pub type Color = [u8; 4];
+
+pub trait TextureGenerator : Send {
+    fn generate(self: Box<Self>) -> Result<Texture, ResourceErr>;
+}
+
+pub struct SolidColor {
+    pub width: u32,
+    pub height: u32,
+    pub color: Color
+}
+
+impl TextureGenerator for SolidColor {
+    fn generate(self: Box<Self>) -> Result<Texture, ResourceErr> {
+        Ok(Texture::Image(RgbaImage::from_pixel(self.width, self.height, Rgba { data: self.color })))
+    }
+}
+
+pub struct Checker {
+    pub width: u32,
+    pub height: u32,
+    pub size: u32,
+    pub color_a: Color,
+    pub color_b: Color
+}
+
+impl TextureGenerator for Checker {
+    fn generate(self: Box<Self>) -> Result<Texture, ResourceErr> {
+        let size = self.size;
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let color = if (x / size + y / size) % 2 == 0 { self.color_a } else { self.color_b };
+            *pixel = Rgba { data: color };
+        }
+        Ok(Texture::Image(image))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GradientDirection {
+    Horizontal,
+    Vertical,
+    Radial
+}
+
+pub struct Gradient {
+    pub width: u32,
+    pub height: u32,
+    pub from: Color,
+    pub to: Color,
+    pub direction: GradientDirection
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let mut color = [0; 4];
+    for c in 0..4 {
+        color[c] = (a[c] as f32 + (b[c] as f32 - a[c] as f32) * t).round() as u8;
+    }
+    color
+}
+
+fn pixel_t(i: u32, n: u32) -> f32 {
+    if n <= 1 { 0.0 } else { i as f32 / (n - 1) as f32 }
+}
+
+impl TextureGenerator for Gradient {
+    fn generate(self: Box<Self>) -> Result<Texture, ResourceErr> {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let t = match self.direction {
+                GradientDirection::Horizontal => pixel_t(x, self.width),
+                GradientDirection::Vertical => pixel_t(y, self.height),
+                GradientDirection::Radial => {
+                    let dx = pixel_t(x, self.width) * 2.0 - 1.0;
+                    let dy = pixel_t(y, self.height) * 2.0 - 1.0;
+                    (dx * dx + dy * dy).sqrt().min(1.0)
+                }
+            };
+            *pixel = Rgba { data: lerp_color(self.from, self.to, t) };
+        }
+        Ok(Texture::Image(image))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoiseKind {
+    Value,
+    Perlin
+}
+
+pub struct Noise {
+    pub width: u32,
+    pub height: u32,
+    pub kind: NoiseKind,
+    pub seed: u32,
+    pub octaves: u32,
+    pub scale: f32,
+    pub from: Color,
+    pub to: Color
+}
+
+fn hash(seed: u32, x: i32, y: i32) -> u32 {
+    let mut h = seed.wrapping_mul(0x27d4eb2d) ^ (x as u32).wrapping_mul(0x85ebca6b) ^ (y as u32).wrapping_mul(0xc2b2ae35);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b3c6d);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297a2d39);
+    h ^= h >> 15;
+    h
+}
+
+fn smooth(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn value_noise(seed: u32, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (tx, ty) = (smooth(x - x0 as f32), smooth(y - y0 as f32));
+    let value = |cx: i32, cy: i32| (hash(seed, cx, cy) & 0xffff) as f32 / 65535.0 * 2.0 - 1.0;
+    lerp(lerp(value(x0, y0), value(x0 + 1, y0), tx), lerp(value(x0, y0 + 1), value(x0 + 1, y0 + 1), tx), ty)
+}
+
+fn perlin_noise(seed: u32, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let gradient = |cx: i32, cy: i32, dx: f32, dy: f32| {
+        let angle = (hash(seed, cx, cy) & 0xffff) as f32 / 65536.0 * 2.0 * ::std::f32::consts::PI;
+        angle.cos() * dx + angle.sin() * dy
+    };
+    let (tx, ty) = (smooth(fx), smooth(fy));
+    let value = lerp(
+        lerp(gradient(x0, y0, fx, fy), gradient(x0 + 1, y0, fx - 1.0, fy), tx),
+        lerp(gradient(x0, y0 + 1, fx, fy - 1.0), gradient(x0 + 1, y0 + 1, fx - 1.0, fy - 1.0), tx),
+        ty);
+    // The range of 2D gradient noise is +-sqrt(0.5)
+    value * ::std::f32::consts::SQRT_2
+}
+
+impl TextureGenerator for Noise {
+    fn generate(self: Box<Self>) -> Result<Texture, ResourceErr> {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let mut value = 0.0;
+            let mut amplitude = 1.0;
+            let mut total_amplitude = 0.0;
+            let mut frequency = self.scale;
+            for octave in 0..self.octaves {
+                let nx = x as f32 / self.width as f32 * frequency;
+                let ny = y as f32 / self.height as f32 * frequency;
+                let seed = self.seed.wrapping_add(octave);
+                value += amplitude * match self.kind {
+                    NoiseKind::Value => value_noise(seed, nx, ny),
+                    NoiseKind::Perlin => perlin_noise(seed, nx, ny)
+                };
+                total_amplitude += amplitude;
+                amplitude *= 0.5;
+                frequency *= 2.0;
+            }
+            let t = (value / total_amplitude * 0.5 + 0.5).max(0.0).min(1.0);
+            *pixel = Rgba { data: lerp_color(self.from, self.to, t) };
+        }
+        Ok(Texture::Image(image))
+    }
+}
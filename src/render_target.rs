@@ -0,0 +1,109 @@
+extern crate gl;
+extern crate glutin;
+
+use backend::*;
+use gl_resources::*;
+
+use gl::types::*;
+use std::rc::Rc;
+
+pub enum RenderTarget {
+    Window(glutin::Window),
+    Headless {
+        context: glutin::HeadlessContext,
+        framebuffer: GLFramebuffer
+    }
+}
+
+impl RenderTarget {
+    pub fn new_window() -> RenderTarget {
+        let window = glutin::Window::new().unwrap();
+
+        unsafe { window.make_current() };
+
+        unsafe {
+            gl::load_with(|symbol| window.get_proc_address(symbol));
+        }
+        RenderTarget::Window(window)
+    }
+    pub fn new_headless(backend: &Rc<RenderBackend>, width: u32, height: u32) -> Result<RenderTarget, String> {
+        let context = try!(glutin::HeadlessRendererBuilder::new(width, height).build()
+            .map_err(|err| format!("Could not create a headless GL context: {:?}", err)));
+
+        unsafe { context.make_current() };
+
+        unsafe {
+            gl::load_with(|symbol| context.get_proc_address(symbol));
+        }
+        let framebuffer = try!(GLFramebuffer::new(backend, width, height));
+        Ok(RenderTarget::Headless {
+            context: context,
+            framebuffer: framebuffer
+        })
+    }
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            &RenderTarget::Window(ref window) => window.get_inner_size().unwrap_or((800, 600)),
+            &RenderTarget::Headless { ref framebuffer, .. } => (framebuffer.width, framebuffer.height)
+        }
+    }
+    pub fn is_headless(&self) -> bool {
+        match self {
+            &RenderTarget::Window(_) => false,
+            &RenderTarget::Headless { .. } => true
+        }
+    }
+    pub fn bind(&self) {
+        let (width, height) = self.size();
+        unsafe {
+            match self {
+                &RenderTarget::Window(_) => {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                    gl::ReadBuffer(gl::BACK);
+                },
+                &RenderTarget::Headless { ref framebuffer, .. } => {
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer.fbo);
+                    gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                }
+            }
+            gl::Viewport(0, 0, width as GLint, height as GLint);
+        }
+    }
+    /// Binds the framebuffer holding the last presented frame for reading.
+    pub fn bind_for_read(&self) {
+        unsafe {
+            match self {
+                &RenderTarget::Window(_) => {
+                    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+                    gl::ReadBuffer(gl::FRONT);
+                },
+                &RenderTarget::Headless { ref framebuffer, .. } => {
+                    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer.fbo);
+                    gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                }
+            }
+        }
+    }
+    pub fn set_title(&self, title: &str) {
+        if let &RenderTarget::Window(ref window) = self {
+            window.set_title(title);
+        }
+    }
+    pub fn present(&self) {
+        match self {
+            &RenderTarget::Window(ref window) => window.swap_buffers(),
+            &RenderTarget::Headless { .. } => unsafe { gl::Finish() }
+        }
+    }
+    pub fn poll_closed(&self) -> bool {
+        if let &RenderTarget::Window(ref window) = self {
+            for event in window.poll_events() {
+                match event {
+                    glutin::Event::Closed => return true,
+                    _ => ()
+                }
+            }
+        }
+        false
+    }
+}
@@ -3,9 +3,12 @@ extern crate gl;
 extern crate image;
 
 use pyramid::document::*;
+use pyramid::pon::*;
 use resources::*;
 use gl_resources::*;
 use shader_uniforms::*;
+use backend::*;
+use mesh::*;
 
 use gl::types::*;
 use std::fs::File;
@@ -17,16 +20,29 @@ use std::mem;
 use std::rc::Rc;
 use std::collections::HashMap;
 use std::cell::RefCell;
+use std::cmp;
+use image::RgbaImage;
+use byteorder::{NativeEndian, ReadBytesExt};
 
 
 
 pub struct Renderer {
+    backend: Rc<RenderBackend>,
     opaque_nodes: Vec<Rc<RefCell<RenderNode>>>,
     translucent_nodes: Vec<Rc<RefCell<RenderNode>>>,
     nodes_by_id: HashMap<u64, Rc<RefCell<RenderNode>>>,
+    frame: u64,
+    skybox: Option<Skybox>,
     pub camera: Matrix4<f32>
 }
 
+#[derive(Debug)]
+pub struct Skybox {
+    pub shader: Rc<GLShaderProgram>,
+    pub vertex_array: Rc<GLVertexArray>,
+    pub texture: Rc<GLTexture>
+}
+
 #[derive(Debug)]
 pub struct RenderNodeResources {
     pub shader: Rc<GLShaderProgram>,
@@ -34,12 +50,37 @@ pub struct RenderNodeResources {
     pub textures: Vec<Rc<GLTexture>>,
 }
 
+/// Declared as `draw_range: { start: 6, count: 12 }` on an entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawRange {
+    pub start: usize,
+    pub count: usize
+}
+
+fn index_field(data: &Pon, field: &str, context: &mut TranslateContext) -> Result<usize, PonTranslateErr> {
+    let value = try!(data.field_as::<i64>(field, context));
+    if value < 0 {
+        return Err(PonTranslateErr::InvalidValue { value: format!("{}: {}, must be at least 0", field, value) });
+    }
+    Ok(value as usize)
+}
+
+impl Translatable<DrawRange> for Pon {
+    fn inner_translate(&self, context: &mut TranslateContext) -> Result<DrawRange, PonTranslateErr> {
+        Ok(DrawRange {
+            start: try!(index_field(self, "start", context)),
+            count: try!(index_field(self, "count", context))
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderNodeConfig {
     pub texture_ids: Vec<String>,
     pub transform: Matrix4<f32>,
     pub uniforms: ShaderUniforms,
-    pub alpha: bool
+    pub alpha: bool,
+    pub draw_range: Option<DrawRange>
 }
 
 #[derive(Debug)]
@@ -51,69 +92,137 @@ pub struct RenderNode {
 
 
 impl Renderer {
-    pub fn new() -> Renderer {
+    pub fn new(backend: Rc<RenderBackend>) -> Renderer {
         Renderer {
+            backend: backend,
             opaque_nodes: vec![],
             translucent_nodes: vec![],
             nodes_by_id: HashMap::new(),
+            frame: 0,
+            skybox: None,
             camera: Matrix4::identity()
         }
     }
     fn draw_node(&self, node: &RenderNode) {
-        unsafe {
-            gl::UseProgram(node.resources.shader.program);
-            gl::BindFragDataLocation(node.resources.shader.program, 0,
-                                     CString::new("out_color").unwrap().as_ptr());
-
-            gl::BindVertexArray(node.resources.vertex_array.vao);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, node.resources.vertex_array.mesh.ebo);
-
-            let view_projection_loc = gl::GetUniformLocation(node.resources.shader.program, CString::new("viewProjection").unwrap().as_ptr());
-            self.camera.gl_write_to_uniform(view_projection_loc);
-            let trans_loc = gl::GetUniformLocation(node.resources.shader.program, CString::new("transform").unwrap().as_ptr());
-            node.config.transform.gl_write_to_uniform(trans_loc);
-
-            for &(ref name, ref uniform) in &node.config.uniforms.0 {
-                let loc = gl::GetUniformLocation(node.resources.shader.program, CString::new(name.to_string()).unwrap().as_ptr());
-                uniform.gl_write_to_uniform(loc);
-            }
-
-            for texi in 0..node.resources.textures.len() {
-                let texture = &node.resources.textures[texi];
-                let name = &node.config.texture_ids[texi];
-                gl::ActiveTexture(gl::TEXTURE0 + texi as GLuint);
-                gl::BindTexture(gl::TEXTURE_2D, texture.texture);
-                let tex_loc = gl::GetUniformLocation(node.resources.shader.program, CString::new(name.to_string()).unwrap().as_ptr());
-                gl::Uniform1i(tex_loc, texi as GLint);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
-            }
-
-            gl::DrawElements(gl::TRIANGLES, node.resources.vertex_array.mesh.nindices, gl::UNSIGNED_INT, ptr::null());
+        let backend = &*self.backend;
+        let program = node.resources.shader.program;
+        backend.use_program(program);
+        backend.bind_frag_data_location(program, 0, "out_color");
+
+        node.resources.vertex_array.mesh.last_drawn.set(self.frame);
+        backend.bind_vertex_array(node.resources.vertex_array.vao);
+        backend.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, node.resources.vertex_array.mesh.ebo);
+
+        let view_projection_loc = backend.get_uniform_location(program, "viewProjection");
+        self.camera.write_to_uniform(backend, view_projection_loc);
+        let trans_loc = backend.get_uniform_location(program, "transform");
+        node.config.transform.write_to_uniform(backend, trans_loc);
+
+        for &(ref name, ref uniform) in &node.config.uniforms.0 {
+            let loc = backend.get_uniform_location(program, name);
+            uniform.write_to_uniform(backend, loc);
         }
-    }
-    pub fn render(&self) {
-        unsafe {
-            gl::DepthMask(gl::TRUE);
-            gl::Enable(gl::DEPTH_TEST);
-            gl::ClearColor(0.3, 0.3, 0.3, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::Disable(gl::BLEND);
-            for node in &self.opaque_nodes {
-                self.draw_node(&*node.borrow());
-            }
-            gl::DepthMask(gl::FALSE);
-            gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            for node in &self.translucent_nodes {
-                self.draw_node(&*node.borrow());
-            }
+
+        for texi in 0..node.resources.textures.len() {
+            let texture = &node.resources.textures[texi];
+            let name = &node.config.texture_ids[texi];
+            texture.last_drawn.set(self.frame);
+            backend.active_texture(texi as GLuint);
+            backend.bind_texture(texture.target, texture.texture);
+            let tex_loc = backend.get_uniform_location(program, name);
+            backend.uniform_1i(tex_loc, texi as GLint);
+        }
+
+        let mesh = &node.resources.vertex_array.mesh;
+        let nindices = mesh.nindices.get() as usize;
+        // The range is clamped, since the indices of a dynamic mesh can change
+        let (start, count) = match node.config.draw_range {
+            Some(range) => {
+                let start = cmp::min(range.start, nindices);
+                (start, cmp::min(range.count, nindices - start))
+            },
+            None => (0, nindices)
         };
+        let index_bytes = if mesh.index_type.get() == gl::UNSIGNED_SHORT { 2 } else { 4 };
+        backend.draw_elements(mesh.mode, count as GLint, mesh.index_type.get(), start * index_bytes);
+    }
+    /// Draws the skybox at the far plane, so it only covers pixels no opaque node was drawn to.
+    fn draw_skybox(&self, skybox: &Skybox) {
+        let backend = &*self.backend;
+        let program = skybox.shader.program;
+        backend.depth_func(gl::LEQUAL);
+        backend.depth_mask(false);
+        backend.use_program(program);
+        backend.bind_frag_data_location(program, 0, "out_color");
+        backend.bind_vertex_array(skybox.vertex_array.vao);
+        backend.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, skybox.vertex_array.mesh.ebo);
+
+        let view_projection_loc = backend.get_uniform_location(program, "viewProjection");
+        self.camera.write_to_uniform(backend, view_projection_loc);
+
+        skybox.texture.last_drawn.set(self.frame);
+        backend.active_texture(0);
+        backend.bind_texture(skybox.texture.target, skybox.texture.texture);
+        let tex_loc = backend.get_uniform_location(program, "skybox");
+        backend.uniform_1i(tex_loc, 0);
+
+        backend.draw_elements(skybox.vertex_array.mesh.mode, skybox.vertex_array.mesh.nindices.get(), skybox.vertex_array.mesh.index_type.get(), 0);
+        backend.depth_mask(true);
+        backend.depth_func(gl::LESS);
+    }
+    pub fn set_skybox(&mut self, skybox: Option<Skybox>) {
+        self.skybox = skybox;
+    }
+    pub fn render(&mut self) {
+        self.frame += 1;
+        let backend = &*self.backend;
+        backend.depth_mask(true);
+        backend.enable(gl::DEPTH_TEST);
+        backend.clear_color(0.3, 0.3, 0.3, 1.0);
+        backend.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        backend.disable(gl::BLEND);
+        for node in &self.opaque_nodes {
+            self.draw_node(&*node.borrow());
+        }
+        if let Some(ref skybox) = self.skybox {
+            self.draw_skybox(skybox);
+        }
+        backend.depth_mask(false);
+        backend.enable(gl::BLEND);
+        backend.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        for node in &self.translucent_nodes {
+            self.draw_node(&*node.borrow());
+        }
+    }
+
+    /// Reads the color buffer of the currently bound read framebuffer. Rows are flipped so that
+    /// the first row of the image is the top of the viewport.
+    pub fn read_color_buffer(&self, width: u32, height: u32) -> RgbaImage {
+        let mut pixels: Vec<u8> = vec![0; (width * height * 4) as usize];
+        self.backend.read_pixels(width, height, gl::RGBA, gl::UNSIGNED_BYTE, &mut pixels);
+        flip_rows(&mut pixels, (width * 4) as usize, height as usize);
+        RgbaImage::from_raw(width, height, pixels).unwrap()
+    }
+    pub fn read_depth_buffer(&self, width: u32, height: u32) -> RgbaImage {
+        let mut depth_bytes: Vec<u8> = vec![0; (width * height * 4) as usize];
+        self.backend.read_pixels(width, height, gl::DEPTH_COMPONENT, gl::FLOAT, &mut depth_bytes);
+        let depth = depth_bytes.chunks(4).map(|mut d| d.read_f32::<NativeEndian>().unwrap());
+        let mut pixels: Vec<u8> = Vec::with_capacity((width * height * 4) as usize);
+        for d in depth {
+            let v = (d.max(0.0).min(1.0) * 255.0) as u8;
+            pixels.push(v);
+            pixels.push(v);
+            pixels.push(v);
+            pixels.push(255);
+        }
+        flip_rows(&mut pixels, (width * 4) as usize, height as usize);
+        RgbaImage::from_raw(width, height, pixels).unwrap()
     }
 
     pub fn add_node(&mut self, node: RenderNode) {
         let has_alpha = node.config.alpha;
         let id = node.id.clone();
+        self.remove_node(&id);
         let node = Rc::new(RefCell::new(node));
         if has_alpha {
             self.translucent_nodes.push(node.clone());
@@ -127,6 +236,13 @@ impl Renderer {
         self.opaque_nodes.retain(|x| x.borrow().id != *key);
         self.nodes_by_id.remove(key);
     }
+    /// Returns false if the node has to be added again with new resources.
+    pub fn update_mesh(&mut self, key: &u64, mesh: &Mesh) -> bool {
+        match self.nodes_by_id.get(key) {
+            Some(node) => node.borrow().resources.vertex_array.mesh.update(mesh),
+            None => false
+        }
+    }
     pub fn set_transform(&mut self, key: &u64, transform: Matrix4<f32>) {
         match self.nodes_by_id.get_mut(key) {
             Some(node) => node.borrow_mut().config.transform = transform,
@@ -134,3 +250,13 @@ impl Renderer {
         }
     }
 }
+
+fn flip_rows(pixels: &mut Vec<u8>, row_len: usize, height: usize) {
+    for y in 0..(height / 2) {
+        let top = y * row_len;
+        let bottom = (height - 1 - y) * row_len;
+        for x in 0..row_len {
+            pixels.swap(top + x, bottom + x);
+        }
+    }
+}
@@ -4,123 +4,562 @@ use pyramid::pon::*;
 use gl_resources::*;
 use pon_to_resource::*;
 use renderer::*;
+use backend::*;
+use file_watcher::*;
 use mesh::*;
 use gl::types::*;
 use pyramid::document::*;
 
 use std::path::PathBuf;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::hash_map::Entry;
 use std::rc::Rc;
+use std::str;
 use std::cell::RefCell;
+use std::mem;
 use image::RgbaImage;
 use ppromise::*;
+use dynamic_texture::*;
 
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceStats {
+    pub n_textures: usize,
+    pub texture_bytes: usize,
+    pub n_meshes: usize,
+    pub mesh_bytes: usize,
+    pub memory_budget: usize
+}
+
+impl ResourceStats {
+    pub fn total_bytes(&self) -> usize {
+        self.texture_bytes + self.mesh_bytes
+    }
+}
+
+/// Bytes of textures and meshes kept cached on the GPU when no budget is set.
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+static SHADER_ERROR_VS: &'static [u8] = include_bytes!("../shaders/error_vs.glsl");
+static SHADER_ERROR_FS: &'static [u8] = include_bytes!("../shaders/error_fs.glsl");
+
 pub struct Resources {
-    pub meshes: HashMap<Pon, Promise<Rc<Mesh>>>,
-    pub gl_meshes: HashMap<Pon, Promise<Rc<GLMesh>>>,
-    pub gl_shader_programs: HashMap<Pon, Promise<Rc<GLShaderProgram>>>,
-    pub gl_vertex_arrays: HashMap<Pon, Promise<Rc<GLVertexArray>>>,
-    pub textures: HashMap<Pon, Promise<Rc<Texture>>>,
-    pub gl_textures: HashMap<Pon, Promise<Rc<GLTexture>>>,
+    pub meshes: HashMap<Pon, Promise<Result<Rc<Mesh>, ResourceErr>>>,
+    pub gl_meshes: HashMap<Pon, Promise<Result<Rc<GLMesh>, ResourceErr>>>,
+    pub gl_shader_programs: HashMap<Pon, Promise<Result<Rc<GLShaderProgram>, ResourceErr>>>,
+    pub gl_vertex_arrays: HashMap<Pon, Promise<Result<Rc<GLVertexArray>, ResourceErr>>>,
+    pub textures: HashMap<Pon, Promise<Result<Rc<Texture>, ResourceErr>>>,
+    pub gl_textures: HashMap<Pon, Promise<Result<Rc<GLTexture>, ResourceErr>>>,
 
     root_path: PathBuf,
-    async_runner: AsyncRunner
+    persistent_keys: HashSet<Pon>,
+    memory_budget: usize,
+    backend: Rc<RenderBackend>,
+    file_watcher: FileWatcher,
+    resource_watches: HashMap<Pon, (String, Option<ResourceIdentity>)>,
+    dynamic_textures: HashMap<Pon, DynamicTexture>,
+    async_runner: AsyncRunner,
+    pending_shader_reloads: Vec<(Pon, Promise<Result<ShaderSource, ResourceErr>>)>,
+
+    fallback_texture: Rc<GLTexture>,
+    fallback_mesh: Rc<GLMesh>,
+    error_shader: Rc<GLShaderProgram>,
+    fallback_vertex_array: Rc<GLVertexArray>
 }
 
 impl Resources {
-    pub fn new(root_path: PathBuf) -> Resources {
+    pub fn new(root_path: PathBuf, backend: Rc<RenderBackend>) -> Resources {
+        let fallback_texture = Rc::new(GLTexture::new(&backend, &checkerboard_texture(), &TextureSampler::default()));
+        let mut fallback_box = Box3::new();
+        fallback_box.layout = Layout::position_texcoord_normal();
+        let fallback_mesh: Mesh = fallback_box.into();
+        let fallback_mesh = Rc::new(GLMesh::new(&backend, &fallback_mesh));
+        let error_shader = Rc::new(GLShaderProgram::new(&backend,
+            &GLShader::new(&backend, str::from_utf8(SHADER_ERROR_VS).unwrap(), gl::VERTEX_SHADER, "bundled error"),
+            &GLShader::new(&backend, str::from_utf8(SHADER_ERROR_FS).unwrap(), gl::FRAGMENT_SHADER, "bundled error")));
+        let fallback_vertex_array = Rc::new(GLVertexArray::new(&backend, &error_shader, &fallback_mesh));
         Resources {
             root_path: root_path,
+            persistent_keys: HashSet::new(),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            backend: backend,
             meshes: HashMap::new(),
             gl_meshes: HashMap::new(),
             gl_shader_programs: HashMap::new(),
             gl_vertex_arrays: HashMap::new(),
             textures: HashMap::new(),
             gl_textures: HashMap::new(),
-            async_runner: AsyncRunner::new_pooled(4)
+            file_watcher: FileWatcher::new(),
+            resource_watches: HashMap::new(),
+            dynamic_textures: HashMap::new(),
+            async_runner: AsyncRunner::new_pooled(4),
+            pending_shader_reloads: vec![],
+            fallback_texture: fallback_texture,
+            fallback_mesh: fallback_mesh,
+            error_shader: error_shader,
+            fallback_vertex_array: fallback_vertex_array
         }
     }
+    /// Never fails; resources that fail to load are replaced by fallbacks, and the errors are returned
+    /// along with them.
     pub fn get(&mut self, document: &mut Document, mesh_key: Pon, shader_program_key: Pon, texture_keys: Vec<Pon>)
-        -> Promise<RenderNodeResources> {
-        let mesh_key = mesh_key.concretize().unwrap();
-        let shader_program_key = shader_program_key.concretize().unwrap();
-        let texture_keys: Vec<Pon> = texture_keys.into_iter().map(|x| x.concretize().unwrap()).collect();
-        let mut gl_shader_program = match self.gl_shader_programs.entry(shader_program_key.clone())  {
+        -> Promise<(RenderNodeResources, Vec<ResourceErr>)> {
+        let mut gl_shader_program = self.get_gl_shader_program(document, &shader_program_key);
+        let mut gl_mesh = self.get_gl_mesh(document, &mesh_key);
+        let gl_vertex_array_key = Pon::Array(vec![mesh_key.clone(), shader_program_key.clone()]);
+        if !self.gl_vertex_arrays.contains_key(&gl_vertex_array_key) {
+            let gl_vertex_array = self.create_vertex_array(&mut gl_shader_program, &mut gl_mesh);
+            self.gl_vertex_arrays.insert(gl_vertex_array_key.clone(), gl_vertex_array);
+        }
+        let gl_vertex_array = self.gl_vertex_arrays.get_mut(&gl_vertex_array_key).unwrap().then(|x| x.clone());
+        let gl_textures = texture_keys.iter().map(|texture_key| self.get_gl_texture(document, texture_key)).collect();
+        self.join_node_resources(gl_shader_program, gl_mesh, gl_vertex_array, gl_textures)
+    }
+    /// The mesh is neither shared nor cached, so that it can be replaced with `GLMesh::update`.
+    pub fn get_dynamic(&mut self, document: &mut Document, mesh_key: Pon, shader_program_key: Pon, texture_keys: Vec<Pon>)
+        -> Promise<(RenderNodeResources, Vec<ResourceErr>)> {
+        let mut gl_shader_program = self.get_gl_shader_program(document, &shader_program_key);
+        let backend = self.backend.clone();
+        let (format, topology) = match pon_to_mesh(&self.root_path, &mesh_key, &mut TranslateContext::from_doc(document)) {
+            Ok(loadable) => (loadable.vertex_format(), loadable.topology()),
+            Err(_) => (VertexFormat::default(), MeshTopology::Triangles)
+        };
+        let mut gl_mesh = self.load_mesh(document, &mesh_key).then_move(move |mesh| {
+            mesh.map(|mesh| Rc::new(GLMesh::with_format(&backend, &mesh, &format, topology, gl::DYNAMIC_DRAW)))
+        });
+        let gl_vertex_array = self.create_vertex_array(&mut gl_shader_program, &mut gl_mesh);
+        let gl_textures = texture_keys.iter().map(|texture_key| self.get_gl_texture(document, texture_key)).collect();
+        self.join_node_resources(gl_shader_program, gl_mesh, gl_vertex_array, gl_textures)
+    }
+    pub fn load_mesh(&mut self, document: &mut Document, key: &Pon) -> Promise<Result<Rc<Mesh>, ResourceErr>> {
+        match pon_to_mesh(&self.root_path, key, &mut TranslateContext::from_doc(document)) {
+            Ok(mut loadable) => loadable.load(&mut self.async_runner),
+            Err(err) => Promise::resolved(Err(ResourceErr::from(err)))
+        }
+    }
+    fn create_vertex_array(&self, gl_shader_program: &mut Promise<Result<Rc<GLShaderProgram>, ResourceErr>>,
+        gl_mesh: &mut Promise<Result<Rc<GLMesh>, ResourceErr>>) -> Promise<Result<Rc<GLVertexArray>, ResourceErr>> {
+        let backend = self.backend.clone();
+        let error_shader = self.error_shader.clone();
+        let fallback_mesh = self.fallback_mesh.clone();
+        (&mut gl_shader_program.then(|x| x.clone()), &mut gl_mesh.then(|x| x.clone())).join().then(move |&(ref gl_shader_program, ref gl_mesh)| {
+            let gl_shader_program = gl_shader_program.clone().unwrap_or(error_shader.clone());
+            let gl_mesh = gl_mesh.clone().unwrap_or(fallback_mesh.clone());
+            Ok(Rc::new(GLVertexArray::new(&backend, &gl_shader_program, &gl_mesh)))
+        })
+    }
+    fn join_node_resources(&self,
+        mut gl_shader_program: Promise<Result<Rc<GLShaderProgram>, ResourceErr>>,
+        mut gl_mesh: Promise<Result<Rc<GLMesh>, ResourceErr>>,
+        mut gl_vertex_array: Promise<Result<Rc<GLVertexArray>, ResourceErr>>,
+        mut gl_textures: Vec<Promise<Result<Rc<GLTexture>, ResourceErr>>>) -> Promise<(RenderNodeResources, Vec<ResourceErr>)> {
+        let error_shader = self.error_shader.clone();
+        let fallback_vertex_array = self.fallback_vertex_array.clone();
+        let fallback_texture = self.fallback_texture.clone();
+        (&mut (&mut gl_shader_program, &mut gl_mesh).join(), &mut gl_vertex_array, &mut gl_textures.join()).join().then_move(move |((sp, mesh), va, txs)| {
+            let mut errors = vec![];
+            let mut shader = match sp {
+                Ok(sp) => sp,
+                Err(err) => {
+                    errors.push(err);
+                    error_shader.clone()
+                }
+            };
+            if let Err(err) = mesh {
+                errors.push(err);
+            }
+            let vertex_array = match va {
+                Ok(va) => {
+                    // The vertex array is still drawn, but inputs it could not bind are reported
+                    errors.extend(va.diagnostics.iter().map(|diagnostic| ResourceErr::Mesh(diagnostic.clone())));
+                    va
+                },
+                Err(err) => {
+                    errors.push(err);
+                    shader = error_shader;
+                    fallback_vertex_array
+                }
+            };
+            let textures = txs.into_iter().map(|tx| match tx {
+                Ok(tx) => tx,
+                Err(err) => {
+                    errors.push(err);
+                    fallback_texture.clone()
+                }
+            }).collect();
+            (RenderNodeResources {
+                shader: shader,
+                vertex_array: vertex_array,
+                textures: textures
+            }, errors)
+        })
+    }
+    pub fn get_texture(&mut self, document: &mut Document, key: &Pon) -> Promise<Result<Rc<GLTexture>, ResourceErr>> {
+        self.get_gl_texture(document, key)
+    }
+    fn get_gl_shader_program(&mut self, document: &mut Document, key: &Pon) -> Promise<Result<Rc<GLShaderProgram>, ResourceErr>> {
+        match self.gl_shader_programs.entry(key.clone())  {
             Entry::Occupied(o) => {
                 o.into_mut()
             },
             Entry::Vacant(v) => {
-                let shader = pon_to_shader(&self.root_path, &shader_program_key, &mut TranslateContext::from_doc(document)).unwrap();
-                let vs = &GLShader::new(&shader.vertex_src, gl::VERTEX_SHADER, &shader.vertex_debug_source_name);
-                let fs = &GLShader::new(&shader.fragment_src, gl::FRAGMENT_SHADER, &shader.fragment_debug_source_name);
-                v.insert(Promise::resolved(Rc::new(GLShaderProgram::new(vs, fs))))
+                let program = match pon_to_shader(&self.root_path, key, &mut TranslateContext::from_doc(document)) {
+                    Ok(loadable) => {
+                        for path in loadable.source_files() {
+                            self.file_watcher.watch(&path, key);
+                        }
+                        let backend = self.backend.clone();
+                        loadable.load(&mut self.async_runner).then_move(move |source| {
+                            let source = try!(source);
+                            GLShaderProgram::from_source(&backend, &source)
+                                .map(|program| Rc::new(program))
+                                .map_err(|err| ResourceErr::Shader(err))
+                        })
+                    },
+                    Err(err) => Promise::resolved(Err(ResourceErr::from(err)))
+                };
+                v.insert(program)
             }
-        }.then(|x| x.clone());
-        let gl_vertex_array_key = Pon::Array(vec![mesh_key.clone(), shader_program_key.clone()]);
-        let mut gl_vertex_array = match self.gl_vertex_arrays.entry(gl_vertex_array_key.clone())  {
+        }.then(|x| x.clone())
+    }
+    fn get_gl_mesh(&mut self, document: &mut Document, key: &Pon) -> Promise<Result<Rc<GLMesh>, ResourceErr>> {
+        match self.gl_meshes.entry(key.clone())  {
             Entry::Occupied(o) => {
                 o.into_mut()
             },
             Entry::Vacant(v) => {
-                let mut gl_mesh = match self.gl_meshes.entry(mesh_key.clone())  {
+                // The component types and topology are needed even if the mesh itself is still cached
+                let loadable = pon_to_mesh(&self.root_path, key, &mut TranslateContext::from_doc(document));
+                let (format, topology) = match loadable {
+                    Ok(ref loadable) => (loadable.vertex_format(), loadable.topology()),
+                    Err(_) => (VertexFormat::default(), MeshTopology::Triangles)
+                };
+                let mut mesh = match self.meshes.entry(key.clone()) {
                     Entry::Occupied(o) => {
                         o.into_mut()
                     },
                     Entry::Vacant(v) => {
-                        let mut mesh = match self.meshes.entry(mesh_key.clone()) {
-                            Entry::Occupied(o) => {
-                                o.into_mut()
+                        let p = match loadable {
+                            Ok(mut loadable) => {
+                                for path in loadable.source_files() {
+                                    self.file_watcher.watch(&path, key);
+                                }
+                                loadable.load(&mut self.async_runner)
                             },
-                            Entry::Vacant(v) => {
-                                let mesh_key = mesh_key.clone();
-                                let root_path = self.root_path.clone();
-                                let p = Promise::resolved(pon_to_mesh(&root_path, &mesh_key, &mut TranslateContext::from_doc(document)).unwrap());
-                                v.insert(p)
-                            }
-                        }.then(|x| x.clone());
-                        v.insert(mesh.then(|mesh| { println!("rc mesh to gl mesh"); Rc::new(GLMesh::new(mesh)) }))
+                            Err(err) => Promise::resolved(Err(ResourceErr::from(err)))
+                        };
+                        v.insert(p)
                     }
                 }.then(|x| x.clone());
-                v.insert((&mut gl_shader_program.then(|x| x.clone()), &mut gl_mesh).join().then(|&(ref gl_shader_program, ref gl_mesh)| {
-                    Rc::new(GLVertexArray::new(gl_shader_program, gl_mesh))
+                let backend = self.backend.clone();
+                v.insert(mesh.then(move |mesh| match *mesh {
+                    Ok(ref mesh) => {
+                        Ok(Rc::new(GLMesh::with_format(&backend, mesh, &format, topology, gl::STATIC_DRAW)))
+                    },
+                    Err(ref err) => Err(err.clone())
                 }))
             }
-        }.then(|x| x.clone());
-        let mut gl_textures = vec![];
-        for texture_key in texture_keys {
-            let gl_texture = match self.gl_textures.entry(texture_key.clone())  {
-                Entry::Occupied(o) => {
-                    o.into_mut()
-                },
-                Entry::Vacant(v) => {
-                    let texture = match self.textures.entry(texture_key.clone()) {
-                        Entry::Occupied(o) => {
-                            o.into_mut()
-                        },
-                        Entry::Vacant(v) => {
-                            let texture_key = texture_key.clone();
-                            let root_path = self.root_path.clone();
-                            let p = pon_to_texture(&root_path, &texture_key, &mut TranslateContext::from_doc(document)).unwrap().load(&mut self.async_runner)
-                                .then_move(|texture| Rc::new(texture));
-                            v.insert(p)
-                        }
-                    };
-                    v.insert(texture.then(|texture| { println!("rc texture to gl texture"); Rc::new(GLTexture::new(texture)) }))
+        }.then(|x| x.clone())
+    }
+    fn get_gl_texture(&mut self, document: &mut Document, key: &Pon) -> Promise<Result<Rc<GLTexture>, ResourceErr>> {
+        match self.gl_textures.entry(key.clone())  {
+            Entry::Occupied(o) => {
+                o.into_mut()
+            },
+            Entry::Vacant(v) => {
+                let texture = match self.textures.entry(key.clone()) {
+                    Entry::Occupied(o) => {
+                        o.into_mut()
+                    },
+                    Entry::Vacant(v) => {
+                        let p = match pon_to_texture(&self.root_path, key, &mut TranslateContext::from_doc(document)) {
+                            Ok(mut loadable) => {
+                                for path in loadable.source_files() {
+                                    self.file_watcher.watch(&path, key);
+                                }
+                                if let Some(texture) = loadable.dynamic_texture() {
+                                    self.dynamic_textures.insert(key.clone(), texture);
+                                }
+                                if let Some(resource_id) = loadable.source_resource() {
+                                    let identity = resource_identity(document, &resource_id).ok();
+                                    self.resource_watches.insert(key.clone(), (resource_id, identity));
+                                }
+                                loadable.load(&mut self.async_runner)
+                                    .then_move(|texture| texture.map(|texture| Rc::new(texture)))
+                            },
+                            Err(err) => Promise::resolved(Err(ResourceErr::from(err)))
+                        };
+                        v.insert(p)
+                    }
+                };
+                let sampler = match pon_to_texture_sampler(key, &mut TranslateContext::from_doc(document)) {
+                    Ok(sampler) => sampler,
+                    Err(err) => return v.insert(Promise::resolved(Err(ResourceErr::from(err)))).then(|x| x.clone())
+                };
+                let backend = self.backend.clone();
+                v.insert(texture.then(move |texture| match *texture {
+                    Ok(ref texture) => {
+                        GLTexture::try_new(&backend, texture, &sampler).map(|texture| Rc::new(texture))
+                    },
+                    Err(ref err) => Err(err.clone())
+                }))
+            }
+        }.then(|x| x.clone())
+    }
+    pub fn update(&mut self) {
+        self.async_runner.try_resolve_all();
+        self.upload_dynamic_textures();
+    }
+    fn upload_dynamic_textures(&mut self) {
+        // Several keys can share a dynamic texture, for instance through a sampled_texture around
+        // it. Its changed region is taken once, when all of its GL textures are loaded, and
+        // uploaded to each of them.
+        let mut uploads: Vec<(DynamicTexture, Vec<Rc<GLTexture>>, bool)> = vec![];
+        for (key, texture) in &self.dynamic_textures {
+            let index = match uploads.iter().position(|&(ref t, _, _)| t.same_texture(texture)) {
+                Some(index) => index,
+                None => {
+                    uploads.push((texture.clone(), vec![], true));
+                    uploads.len() - 1
+                }
+            };
+            if let Some(promise) = self.gl_textures.get(key) {
+                match promise.value() {
+                    Some(&Ok(ref gl_texture)) => uploads[index].1.push(gl_texture.clone()),
+                    Some(&Err(_)) => {},
+                    None => uploads[index].2 = false
+                }
+            }
+        }
+        for (texture, gl_textures, loaded) in uploads {
+            if !loaded {
+                continue;
+            }
+            if let Some((rect, region)) = texture.take_dirty() {
+                for gl_texture in &gl_textures {
+                    gl_texture.update_region(rect.x, rect.y, &region);
+                }
+            }
+        }
+    }
+    /// Shader programs keep the old program until the new source has compiled, and only then is
+    /// their key returned.
+    pub fn reload_changed_files(&mut self, document: &mut Document) -> Vec<Pon> {
+        let mut reloaded = self.finish_shader_reloads();
+        let mut changed = self.file_watcher.poll();
+        for (key, &(ref resource_id, ref identity)) in &self.resource_watches {
+            let unchanged = match *identity {
+                Some(ref identity) => identity.matches(document, resource_id),
+                None => resource_identity(document, resource_id).is_err()
+            };
+            if !unchanged && !changed.contains(key) {
+                println!("Resource changed: {}", resource_id);
+                changed.push(key.clone());
+            }
+        }
+        for key in changed {
+            if self.gl_shader_programs.contains_key(&key) {
+                self.reload_shader_program(document, &key);
+                continue;
+            }
+            self.textures.remove(&key);
+            self.gl_textures.remove(&key);
+            self.resource_watches.remove(&key);
+            self.dynamic_textures.remove(&key);
+            self.meshes.remove(&key);
+            if self.gl_meshes.remove(&key).is_some() {
+                self.remove_vertex_arrays(|mesh_key, _| *mesh_key == key);
+            }
+            // Keys that previously failed to load have already been evicted, but their entities
+            // still need to be added again
+            reloaded.push(key);
+        }
+        reloaded
+    }
+    fn reload_shader_program(&mut self, document: &mut Document, key: &Pon) {
+        println!("Reloading shader program {}", key.to_string());
+        match pon_to_shader(&self.root_path, key, &mut TranslateContext::from_doc(document)) {
+            Ok(loadable) => {
+                let source = loadable.load(&mut self.async_runner);
+                self.pending_shader_reloads.retain(|&(ref k, _)| k != key);
+                self.pending_shader_reloads.push((key.clone(), source));
+            },
+            Err(err) => println!("Failed to reload shader program, keeping the old one: {:?}", err)
+        }
+    }
+    fn finish_shader_reloads(&mut self) -> Vec<Pon> {
+        let mut reloaded = vec![];
+        let pending = mem::replace(&mut self.pending_shader_reloads, vec![]);
+        for (key, source) in pending {
+            let program = match source.value() {
+                Some(&Ok(ref source)) => GLShaderProgram::from_source(&self.backend, source),
+                Some(&Err(ref err)) => Err(err.to_string()),
+                None => {
+                    self.pending_shader_reloads.push((key, source));
+                    continue;
                 }
             };
-            gl_textures.push(gl_texture.then(|x| x.clone()));
+            match program {
+                Ok(program) => {
+                    self.gl_shader_programs.insert(key.clone(), Promise::resolved(Ok(Rc::new(program))));
+                    self.remove_vertex_arrays(|_, shader_key| *shader_key == key);
+                    reloaded.push(key);
+                },
+                Err(err) => println!("Failed to reload shader program, keeping the old one: {}", err)
+            }
+        }
+        reloaded
+    }
+    fn remove_vertex_arrays<F: Fn(&Pon, &Pon) -> bool>(&mut self, predicate: F) {
+        let keys: Vec<Pon> = self.gl_vertex_arrays.keys().filter(|key| match *key {
+            &Pon::Array(ref arr) => predicate(&arr[0], &arr[1]),
+            _ => false
+        }).cloned().collect();
+        for key in keys {
+            self.gl_vertex_arrays.remove(&key);
+        }
+    }
+    pub fn add_persistent_shader_program(&mut self, key: Pon, program: GLShaderProgram) {
+        self.persistent_keys.insert(key.clone());
+        self.gl_shader_programs.insert(key, Promise::resolved(Ok(Rc::new(program))));
+    }
+    /// A budget of 0 evicts unused entries right away.
+    pub fn set_memory_budget(&mut self, bytes: usize) {
+        self.memory_budget = bytes;
+    }
+    pub fn stats(&self) -> ResourceStats {
+        let (n_textures, texture_bytes) = memory_usage(&self.gl_textures);
+        let (n_meshes, mesh_bytes) = memory_usage(&self.gl_meshes);
+        ResourceStats {
+            n_textures: n_textures,
+            texture_bytes: texture_bytes,
+            n_meshes: n_meshes,
+            mesh_bytes: mesh_bytes,
+            memory_budget: self.memory_budget
         }
-        (&mut gl_shader_program, &mut gl_vertex_array, &mut gl_textures.join()).join().then_move(|(sp, va, txs)| {
-            RenderNodeResources {
-                shader: sp,
-                vertex_array: va,
-                textures: txs
+    }
+    /// Entries that are still loading are kept.
+    pub fn collect_garbage(&mut self) {
+        // Vertex arrays hold on to their GL mesh, so they have to go first
+        evict_unused(&mut self.gl_vertex_arrays, &self.persistent_keys);
+        let mut evicted = evict_unused(&mut self.gl_shader_programs, &self.persistent_keys);
+        // Failed entries are retried the next time they are requested. They stay watched, so that
+        // fixing the file on disk, or adding the missing resource, reloads them.
+        let mut failed = evict_failed(&mut self.gl_meshes);
+        failed.extend(evict_failed(&mut self.gl_shader_programs));
+        evict_failed(&mut self.gl_textures);
+        evict_failed(&mut self.gl_vertex_arrays);
+        // Vertex arrays of a failed mesh or shader were built from the fallbacks, and would keep
+        // being used after the entry loads again
+        self.remove_vertex_arrays(|mesh_key, shader_key| failed.contains(mesh_key) || failed.contains(shader_key));
+
+        let mut usage = self.stats().total_bytes();
+        if usage > self.memory_budget {
+            let mut candidates: Vec<(u64, usize, bool, Pon)> = vec![];
+            for (last_drawn, size, key) in unused_gpu_resources(&self.gl_meshes, &self.persistent_keys) {
+                candidates.push((last_drawn, size, false, key));
+            }
+            for (last_drawn, size, key) in unused_gpu_resources(&self.gl_textures, &self.persistent_keys) {
+                candidates.push((last_drawn, size, true, key));
             }
+            candidates.sort_by(|a, b| a.0.cmp(&b.0));
+            for (_, size, is_texture, key) in candidates {
+                if usage <= self.memory_budget {
+                    break;
+                }
+                println!("Evicting unused resource {}", key.to_string());
+                if is_texture {
+                    self.gl_textures.remove(&key);
+                } else {
+                    self.gl_meshes.remove(&key);
+                }
+                evicted.push(key);
+                usage -= size;
+            }
+            if usage > self.memory_budget && self.memory_budget > 0 {
+                println!("Resources in use exceed the memory budget: {} > {} bytes", usage, self.memory_budget);
+            }
+        }
+
+        // CPU side data is only kept around for as long as its GL counterpart
+        let gl_meshes = &self.gl_meshes;
+        let meshes: Vec<Pon> = self.meshes.keys().filter(|key| !gl_meshes.contains_key(key)).cloned().collect();
+        for key in meshes {
+            self.meshes.remove(&key);
+        }
+        let gl_textures = &self.gl_textures;
+        let textures: Vec<Pon> = self.textures.keys().filter(|key| !gl_textures.contains_key(key)).cloned().collect();
+        for key in textures {
+            self.textures.remove(&key);
+            self.dynamic_textures.remove(&key);
+        }
+        for key in evicted {
+            self.file_watcher.unwatch(&key);
+            self.resource_watches.remove(&key);
+        }
+    }
+}
+
+fn is_unused<T>(key: &Pon, promise: &Promise<Result<Rc<T>, ResourceErr>>, persistent_keys: &HashSet<Pon>) -> bool {
+    !persistent_keys.contains(key) && match promise.value() {
+        Some(value) => match *value {
+            Ok(ref value) => Rc::strong_count(value) == 1,
+            Err(_) => false
+        },
+        None => false
+    }
+}
+
+fn evict_unused<T>(map: &mut HashMap<Pon, Promise<Result<Rc<T>, ResourceErr>>>, persistent_keys: &HashSet<Pon>) -> Vec<Pon> {
+    let unused: Vec<Pon> = map.iter()
+        .filter(|&(key, promise)| is_unused(key, promise, persistent_keys))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in &unused {
+        println!("Evicting unused resource {}", key.to_string());
+        map.remove(key);
+    }
+    unused
+}
+
+fn evict_failed<T>(map: &mut HashMap<Pon, Promise<Result<Rc<T>, ResourceErr>>>) -> Vec<Pon> {
+    let failed: Vec<Pon> = map.iter()
+        .filter(|&(_, promise)| match promise.value() {
+            Some(value) => value.is_err(),
+            None => false
         })
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in &failed {
+        map.remove(key);
     }
-    pub fn update(&mut self) {
-        self.async_runner.try_resolve_all();
+    failed
+}
+
+fn unused_gpu_resources<T: GPUResource>(map: &HashMap<Pon, Promise<Result<Rc<T>, ResourceErr>>>, persistent_keys: &HashSet<Pon>) -> Vec<(u64, usize, Pon)> {
+    map.iter()
+        .filter(|&(key, promise)| is_unused(key, promise, persistent_keys))
+        .filter_map(|(key, promise)| match promise.value() {
+            Some(value) => match *value {
+                Ok(ref value) => Some((value.last_drawn(), value.size_bytes(), key.clone())),
+                Err(_) => None
+            },
+            None => None
+        })
+        .collect()
+}
+
+fn memory_usage<T: GPUResource>(map: &HashMap<Pon, Promise<Result<Rc<T>, ResourceErr>>>) -> (usize, usize) {
+    let mut count = 0;
+    let mut bytes = 0;
+    for promise in map.values() {
+        if let Some(value) = promise.value() {
+            if let Ok(ref value) = *value {
+                count += 1;
+                bytes += value.size_bytes();
+            }
+        }
     }
+    (count, bytes)
 }
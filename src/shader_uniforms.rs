@@ -6,33 +6,28 @@ use cgmath::*;
 use std::collections::HashMap;
 use pyramid::pon::*;
 use std::fmt::Debug;
+use backend::*;
 
 #[derive(Debug)]
 pub struct ShaderUniforms(pub Vec<(String, Box<ShaderUniform>)>);
 
 pub trait ShaderUniform : Debug {
-    fn gl_write_to_uniform(&self, uniform_location: GLint);
+    fn write_to_uniform(&self, backend: &RenderBackend, uniform_location: GLint);
 }
 impl ShaderUniform for f32 {
-    fn gl_write_to_uniform(&self, uniform_location: GLint) {
-        unsafe {
-            gl::Uniform1f(uniform_location, *self);
-        }
+    fn write_to_uniform(&self, backend: &RenderBackend, uniform_location: GLint) {
+        backend.uniform_1f(uniform_location, *self);
     }
 }
 impl ShaderUniform for Vector3<f32> {
-    fn gl_write_to_uniform(&self, uniform_location: GLint) {
-        unsafe {
-            gl::Uniform3f(uniform_location, self.x, self.y, self.z);
-        }
+    fn write_to_uniform(&self, backend: &RenderBackend, uniform_location: GLint) {
+        backend.uniform_3f(uniform_location, self.x, self.y, self.z);
     }
 }
 impl ShaderUniform for Matrix4<f32> {
-    fn gl_write_to_uniform(&self, uniform_location: GLint) {
-        unsafe {
-            let t: [f32; 16] = mem::transmute(*self);
-            gl::UniformMatrix4fv(uniform_location, 1, gl::FALSE, t.as_ptr());
-        }
+    fn write_to_uniform(&self, backend: &RenderBackend, uniform_location: GLint) {
+        let t: [f32; 16] = unsafe { mem::transmute(*self) };
+        backend.uniform_matrix_4fv(uniform_location, &t);
     }
 }
 
@@ -0,0 +1,57 @@
+extern crate gl;
+extern crate image;
+extern crate pyramid_viewport;
+
+use pyramid_viewport::backend::*;
+use pyramid_viewport::dynamic_texture::*;
+use pyramid_viewport::gl_resources::*;
+use pyramid_viewport::pon_to_resource::*;
+
+use image::{RgbaImage, Rgba};
+use std::rc::Rc;
+
+#[test]
+fn dirty_regions_are_merged_until_taken() {
+    let texture = DynamicTexture::new(RgbaImage::new(16, 16));
+    texture.update_region(2, 3, &RgbaImage::from_pixel(2, 2, Rgba { data: [255, 0, 0, 255] })).unwrap();
+    texture.update_region(8, 1, &RgbaImage::from_pixel(1, 1, Rgba { data: [0, 255, 0, 255] })).unwrap();
+
+    let (rect, region) = texture.take_dirty().unwrap();
+    assert_eq!(rect, DirtyRect { x: 2, y: 1, width: 7, height: 4 });
+    assert_eq!(region.dimensions(), (7, 4));
+    assert_eq!(region.get_pixel(0, 2).data, [255, 0, 0, 255]);
+    assert_eq!(region.get_pixel(6, 0).data, [0, 255, 0, 255]);
+    assert!(texture.take_dirty().is_none());
+}
+
+#[test]
+fn updates_outside_the_texture_are_rejected() {
+    let texture = DynamicTexture::new(RgbaImage::new(4, 4));
+    assert!(texture.update_region(3, 3, &RgbaImage::new(2, 2)).is_err());
+    assert!(texture.update_region(u32::max_value(), 0, &RgbaImage::new(2, 2)).is_err());
+    assert!(texture.update_region(0, u32::max_value(), &RgbaImage::new(2, 2)).is_err());
+    assert!(texture.set_image(RgbaImage::new(8, 8)).is_err());
+    assert!(texture.take_dirty().is_none());
+}
+
+#[test]
+fn clones_refer_to_the_same_texture() {
+    let texture = DynamicTexture::new(RgbaImage::new(4, 4));
+    assert!(texture.same_texture(&texture.clone()));
+    assert!(!texture.same_texture(&DynamicTexture::new(RgbaImage::new(4, 4))));
+}
+
+#[test]
+fn regions_are_uploaded_with_tex_sub_image() {
+    let recording = Rc::new(RecordingBackend::new());
+    let backend: Rc<RenderBackend> = recording.clone();
+    let gl_texture = GLTexture::new(&backend, &Texture::Image(RgbaImage::new(16, 16)), &TextureSampler::default());
+    recording.clear_commands();
+
+    gl_texture.update_region(2, 3, &RgbaImage::new(4, 5));
+
+    let commands = recording.commands();
+    assert!(!commands.iter().any(|c| match *c { RenderCommand::TexImage2D { .. } => true, _ => false }));
+    assert!(commands.contains(&RenderCommand::TexSubImage2D {
+        target: gl::TEXTURE_2D, x: 2, y: 3, width: 4, height: 5, format: gl::RGBA, ty: gl::UNSIGNED_BYTE }));
+}
@@ -0,0 +1,205 @@
+//! Golden image tests. Each scene in `tests/golden/scenes` is rendered headless and compared
+//! against `tests/golden/reference/<scene>.png`.
+//!
+//! Run with a software GL implementation (e.g. OSMesa or `LIBGL_ALWAYS_SOFTWARE=1`) so the
+//! output is reproducible. Set `PYRAMID_BLESS_GOLDEN=1` to (re)write the reference images.
+//! Failing scenes write `<scene>.actual.png` and `<scene>.diff.png` to `target/golden`.
+
+extern crate pyramid;
+extern crate pyramid_viewport;
+extern crate image;
+
+use pyramid::interface::*;
+use pyramid::document::*;
+use pyramid::system::*;
+use pyramid_viewport::*;
+
+use image::{RgbaImage, Rgba, GenericImage};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+const FRAMES: u32 = 3;
+const MAX_UPDATES: u32 = 1000;
+const DEFAULT_TOLERANCE: u8 = 2;
+
+/// Wraps the viewport and reads back the color buffer once all entities are loaded and a fixed
+/// number of frames have been rendered.
+struct GoldenCapture {
+    viewport: ViewportSubSystem,
+    frames_left: u32,
+    result: Rc<RefCell<Option<RgbaImage>>>
+}
+
+impl ISubSystem for GoldenCapture {
+    fn on_property_value_change(&mut self, system: &mut System, prop_refs: &Vec<PropRef>) {
+        self.viewport.on_property_value_change(system, prop_refs);
+    }
+    fn update(&mut self, system: &mut System) {
+        self.viewport.update(system);
+        if self.viewport.is_loading() || self.result.borrow().is_some() {
+            return;
+        }
+        if self.frames_left > 0 {
+            self.frames_left -= 1;
+            return;
+        }
+        *self.result.borrow_mut() = Some(self.viewport.read_color_buffer());
+    }
+}
+
+fn scenes_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/scenes")
+}
+
+fn render_scene(name: &str) -> RgbaImage {
+    let viewport = match ViewportSubSystem::new_headless(scenes_path(), WIDTH, HEIGHT) {
+        Ok(viewport) => viewport,
+        Err(err) => panic!("Could not create a headless viewport: {}", err)
+    };
+    let result = Rc::new(RefCell::new(None));
+    let mut system = System::new();
+    system.add_subsystem(Box::new(GoldenCapture {
+        viewport: viewport,
+        frames_left: FRAMES,
+        result: result.clone()
+    }));
+    system.load_document_from_file(&scenes_path().join(format!("{}.pml", name)));
+    for _ in 0..MAX_UPDATES {
+        system.update();
+        if result.borrow().is_some() {
+            break;
+        }
+    }
+    let image = result.borrow_mut().take();
+    match image {
+        Some(image) => image,
+        None => panic!("Scene {} did not finish loading within {} updates", name, MAX_UPDATES)
+    }
+}
+
+/// Compares two images channel by channel. Returns the number of pixels differing by more than
+/// `tolerance` in any channel, and an image highlighting those pixels in red.
+fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> (u32, RgbaImage) {
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut n_failed = 0;
+    for (x, y, pixel) in actual.enumerate_pixels() {
+        let expected_pixel = expected.get_pixel(x, y);
+        let mut failed = false;
+        for c in 0..4 {
+            let d = (pixel.data[c] as i32 - expected_pixel.data[c] as i32).abs();
+            if d > tolerance as i32 {
+                failed = true;
+            }
+        }
+        if failed {
+            n_failed += 1;
+            diff.put_pixel(x, y, Rgba { data: [255, 0, 0, 255] });
+        } else {
+            let gray = ((pixel.data[0] as u32 + pixel.data[1] as u32 + pixel.data[2] as u32) / 12) as u8;
+            diff.put_pixel(x, y, Rgba { data: [gray, gray, gray, 255] });
+        }
+    }
+    (n_failed, diff)
+}
+
+fn check_golden(name: &str, tolerance: u8) {
+    let actual = render_scene(name);
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/golden/reference/{}.png", name));
+    if env::var("PYRAMID_BLESS_GOLDEN").is_ok() {
+        actual.save(&reference_path).unwrap();
+        println!("Blessed {:?}", reference_path);
+        return;
+    }
+    let output_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden");
+    fs::create_dir_all(&output_path).unwrap();
+    let actual_path = output_path.join(format!("{}.actual.png", name));
+    let expected = match image::open(&reference_path) {
+        Ok(expected) => expected.to_rgba(),
+        Err(err) => {
+            actual.save(&actual_path).unwrap();
+            panic!("Missing reference {:?} ({:?}). Output written to {:?}; rerun with PYRAMID_BLESS_GOLDEN=1 to accept it.",
+                reference_path, err, actual_path);
+        }
+    };
+    if actual.dimensions() != expected.dimensions() {
+        actual.save(&actual_path).unwrap();
+        panic!("{}: size {:?} does not match reference size {:?}", name, actual.dimensions(), expected.dimensions());
+    }
+    let (n_failed, diff) = compare(&actual, &expected, tolerance);
+    if n_failed > 0 {
+        let diff_path = output_path.join(format!("{}.diff.png", name));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!("{}: {} pixels differ by more than {}. See {:?} and {:?}", name, n_failed, tolerance, actual_path, diff_path);
+    }
+}
+
+#[test]
+fn golden_static_mesh() {
+    check_golden("static_mesh", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_grid_mesh() {
+    check_golden("grid_mesh", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_box_mesh() {
+    check_golden("box_mesh", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_primitives() {
+    check_golden("primitives", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_mesh_from_file() {
+    check_golden("mesh_from_file", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_gltf() {
+    check_golden("gltf", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_static_texture() {
+    check_golden("static_texture", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_texture_from_file() {
+    check_golden("texture_from_file", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_procedural_textures() {
+    check_golden("procedural_textures", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_skybox() {
+    check_golden("skybox", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_alpha() {
+    check_golden("alpha", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_uniforms() {
+    check_golden("uniforms", DEFAULT_TOLERANCE);
+}
+
+#[test]
+fn golden_draw_range() {
+    check_golden("draw_range", DEFAULT_TOLERANCE);
+}
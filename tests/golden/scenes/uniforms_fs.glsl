@@ -0,0 +1,13 @@
+#version 150
+
+in vec2 Texcoord;
+
+out vec4 out_color;
+
+uniform sampler2D diffuse;
+uniform float brightness;
+uniform vec3 tint;
+
+void main() {
+   out_color = vec4(texture(diffuse, Texcoord).rgb * brightness + tint * 0.5, 1.0);
+}
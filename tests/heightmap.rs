@@ -0,0 +1,77 @@
+extern crate mesh;
+extern crate pyramid_viewport;
+
+use pyramid_viewport::heightmap::*;
+use pyramid_viewport::pon_to_resource::Texture;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+
+use mesh::*;
+
+/// A 5x3 ramp rising by 1 per sample along x.
+fn ramp() -> Heightmap {
+    let data = (0..15).map(|i| (i % 5) as f32).collect();
+    Heightmap::from_texture(Texture::Floats { width: 5, height: 3, channels: 1, half: false, data: data }).unwrap()
+}
+
+fn options(chunk: Option<TerrainChunk>) -> TerrainOptions {
+    TerrainOptions {
+        layout: Layout::position_texcoord_normal(),
+        horizontal_scale: 2.0,
+        vertical_scale: 0.5,
+        chunk: chunk
+    }
+}
+
+fn vertex(mesh: &Mesh, i: usize) -> &[f32] {
+    &mesh.vertex_data[(i * mesh.layout.stride)..((i + 1) * mesh.layout.stride)]
+}
+
+#[test]
+fn heightmap_mesh_is_displaced_and_centered() {
+    let mesh = heightmap_to_mesh(&ramp(), &options(None)).unwrap();
+    assert_eq!(mesh.vertex_data.len() / mesh.layout.stride, 15);
+    assert_eq!(mesh.element_data.len(), 4 * 2 * 6);
+    // position, texcoord, normal
+    assert_eq!(&vertex(&mesh, 0)[0..5], &[-4.0, 0.0, -2.0, 0.0, 0.0]);
+    assert_eq!(&vertex(&mesh, 14)[0..5], &[4.0, 2.0, 2.0, 1.0, 1.0]);
+    // The slope is 0.5 / 2.0
+    let normal = &vertex(&mesh, 7)[5..8];
+    let length = (1.0f32 + 0.25 * 0.25).sqrt();
+    assert!((normal[0] + 0.25 / length).abs() < 1e-6 && (normal[1] - 1.0 / length).abs() < 1e-6 && normal[2] == 0.0);
+}
+
+#[test]
+fn heightmap_chunks_share_their_edges() {
+    let heightmap = ramp();
+    assert_eq!(heightmap.chunk_count(3), Some((2, 1)));
+    assert_eq!(heightmap.chunk_count(u32::max_value()), Some((1, 1)));
+    assert_eq!(heightmap.chunk_count(0), None);
+    let whole = heightmap_to_mesh(&heightmap, &options(None)).unwrap();
+    let first = heightmap_to_mesh(&heightmap, &options(Some(TerrainChunk { x: 0, y: 0, size: 3 }))).unwrap();
+    let second = heightmap_to_mesh(&heightmap, &options(Some(TerrainChunk { x: 1, y: 0, size: 3 }))).unwrap();
+    assert_eq!(first.vertex_data.len() / first.layout.stride, 4 * 3);
+    assert_eq!(second.vertex_data.len() / second.layout.stride, 2 * 3);
+    // The last column of the first chunk is the first column of the second
+    assert_eq!(vertex(&first, 3), vertex(&second, 0));
+    assert_eq!(vertex(&second, 0), vertex(&whole, 3));
+    assert!(heightmap_to_mesh(&heightmap, &options(Some(TerrainChunk { x: 2, y: 0, size: 3 }))).is_err());
+    assert!(heightmap_to_mesh(&heightmap, &options(Some(TerrainChunk { x: 0, y: 0, size: 0 }))).is_err());
+    assert!(heightmap_to_mesh(&heightmap, &options(Some(TerrainChunk { x: u32::max_value(), y: 0, size: 3 }))).is_err());
+    let large = heightmap_to_mesh(&heightmap, &options(Some(TerrainChunk { x: 0, y: 0, size: u32::max_value() }))).unwrap();
+    assert_eq!(large.vertex_data.len(), whole.vertex_data.len());
+}
+
+#[test]
+fn oversized_heightmap_headers_are_rejected() {
+    let path = env::temp_dir().join("pyramid_viewport_oversized.dhm");
+    let mut data = b"DHMX".to_vec();
+    // Version 1, 65536x65536 samples with 4 channels of 32-bit floats, and no data
+    for value in &[1u32, 65536, 65536, 4, 0] {
+        data.extend([*value as u8, (*value >> 8) as u8, (*value >> 16) as u8, (*value >> 24) as u8].iter().cloned());
+    }
+    File::create(&path).unwrap().write_all(&data).unwrap();
+    assert!(load_heightmap(&path).is_err());
+}
@@ -0,0 +1,75 @@
+extern crate mesh;
+extern crate pyramid_viewport;
+
+use pyramid_viewport::mesh_processing::*;
+
+use mesh::*;
+
+/// A quad in the XY plane made of two triangles that don't share vertices.
+fn split_quad() -> Mesh {
+    Mesh {
+        layout: Layout::new(vec![AttributeSpec("position".to_string(), 3), AttributeSpec("texcoord".to_string(), 2)]),
+        vertex_data: vec![
+            0.0, 0.0, 0.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 1.0, 0.0,
+            1.0, 1.0, 0.0, 1.0, 1.0,
+            0.0, 0.0, 0.0, 0.0, 0.0,
+            1.0, 1.0, 0.0, 1.0, 1.0,
+            0.0, 1.0, 0.0, 0.0, 1.0],
+        element_data: vec![0, 1, 2, 3, 4, 5]
+    }
+}
+
+fn attribute(mesh: &Mesh, name: &str, vertex: usize) -> Vec<f32> {
+    let attribute = mesh.layout.attributes.iter().find(|a| a.name == name).unwrap();
+    let start = vertex * mesh.layout.stride + attribute.offset;
+    mesh.vertex_data[start..(start + attribute.size)].to_vec()
+}
+
+#[test]
+fn weld_merges_duplicate_vertices() {
+    let mesh = process_mesh(split_quad(), &[MeshProcess::Weld { epsilon: DEFAULT_WELD_EPSILON }]).unwrap();
+    assert_eq!(mesh.vertex_data.len() / mesh.layout.stride, 4);
+    assert_eq!(mesh.element_data, vec![0, 1, 2, 0, 2, 3]);
+}
+
+#[test]
+fn normals_are_added_to_the_layout() {
+    let mesh = process_mesh(split_quad(), &[MeshProcess::SmoothNormals]).unwrap();
+    assert_eq!(mesh.layout.stride, 8);
+    for vertex in 0..6 {
+        assert_eq!(attribute(&mesh, "normal", vertex), vec![0.0, 0.0, 1.0]);
+    }
+
+    let flat = process_mesh(split_quad(), &[MeshProcess::Weld { epsilon: DEFAULT_WELD_EPSILON }, MeshProcess::FlatNormals]).unwrap();
+    assert_eq!(flat.vertex_data.len() / flat.layout.stride, 6);
+    assert_eq!(attribute(&flat, "normal", 5), vec![0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn tangents_follow_the_texture_coordinates() {
+    let steps = [MeshProcess::SmoothNormals, MeshProcess::Tangents];
+    let mesh = process_mesh(split_quad(), &steps).unwrap();
+    for vertex in 0..6 {
+        assert_eq!(attribute(&mesh, "tangent", vertex), vec![1.0, 0.0, 0.0, 1.0]);
+    }
+
+    let mirrored = process_mesh(split_quad(), &[MeshProcess::FlipWinding, MeshProcess::SmoothNormals, MeshProcess::Tangents]).unwrap();
+    assert_eq!(attribute(&mirrored, "normal", 0), vec![0.0, 0.0, -1.0]);
+    assert_eq!(attribute(&mirrored, "tangent", 0), vec![1.0, 0.0, 0.0, -1.0]);
+}
+
+#[test]
+fn tangents_need_normals() {
+    assert!(process_mesh(split_quad(), &[MeshProcess::Tangents]).is_err());
+}
+
+#[test]
+fn malformed_meshes_are_errors() {
+    let mut out_of_range = split_quad();
+    out_of_range.element_data[5] = 6;
+    assert!(process_mesh(out_of_range, &[MeshProcess::SmoothNormals]).is_err());
+
+    let empty_layout = Mesh { layout: Layout::new(vec![]), vertex_data: vec![], element_data: vec![] };
+    assert!(process_mesh(empty_layout, &[MeshProcess::Weld { epsilon: DEFAULT_WELD_EPSILON }]).is_err());
+}
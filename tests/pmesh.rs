@@ -0,0 +1,48 @@
+extern crate mesh;
+extern crate pyramid_viewport;
+
+use pyramid_viewport::pmesh::*;
+
+use mesh::*;
+
+fn quad() -> Mesh {
+    Mesh {
+        layout: Layout::new(vec![AttributeSpec("position".to_string(), 3), AttributeSpec("texcoord".to_string(), 2)]),
+        vertex_data: vec![
+            -0.5, -0.5, 0.0, 0.0, 0.0,
+            0.5, -0.5, 0.0, 1.0, 0.0,
+            0.5, 0.5, 0.0, 1.0, 1.0,
+            -0.5, 0.5, 0.0, 0.0, 1.0],
+        element_data: vec![0, 1, 2, 0, 2, 3]
+    }
+}
+
+fn write(mesh: &Mesh) -> Vec<u8> {
+    let mut data = vec![];
+    write_pmesh(mesh, &mut data).unwrap();
+    data
+}
+
+#[test]
+fn pmesh_round_trip() {
+    let mesh = quad();
+    let read = read_pmesh(&write(&mesh)).unwrap();
+    assert_eq!(read.vertex_data, mesh.vertex_data);
+    assert_eq!(read.element_data, mesh.element_data);
+    assert_eq!(read.layout.stride, 5);
+    let attributes: Vec<(String, usize, usize)> = read.layout.attributes.iter().map(|a| (a.name.clone(), a.size, a.offset)).collect();
+    assert_eq!(attributes, vec![("position".to_string(), 3, 0), ("texcoord".to_string(), 2, 3)]);
+}
+
+#[test]
+fn pmesh_rejects_other_versions() {
+    let mut data = write(&quad());
+    data[4] = 2;
+    assert!(read_pmesh(&data).is_err());
+}
+
+#[test]
+fn pmesh_rejects_truncated_files() {
+    let data = write(&quad());
+    assert!(read_pmesh(&data[..data.len() - 4]).is_err());
+}
@@ -0,0 +1,55 @@
+//! Translation of the mesh fields that apply to every type of mesh, and of entity draw ranges.
+
+extern crate pyramid;
+extern crate pyramid_viewport;
+
+use pyramid::pon::*;
+use pyramid_viewport::pon_to_resource::*;
+use pyramid_viewport::renderer::DrawRange;
+
+use std::path::Path;
+
+#[test]
+fn gltf_primitive_and_topology_are_separate_fields() {
+    let node = Pon::from_string("gltf_mesh { path: \"quad.gltf\", mesh: \"quad\", primitive: 1, topology: \"lines\" }").unwrap();
+    let mesh = match pon_to_mesh(Path::new("."), &node, &mut TranslateContext::empty()) {
+        Ok(mesh) => mesh,
+        Err(err) => panic!("Failed to translate gltf_mesh: {:?}", err)
+    };
+    assert_eq!(mesh.topology(), MeshTopology::Lines);
+
+    let node = Pon::from_string("gltf_mesh { path: \"quad.gltf\", mesh: \"quad\", primitive: 1 }").unwrap();
+    let mesh = pon_to_mesh(Path::new("."), &node, &mut TranslateContext::empty()).ok().unwrap();
+    assert_eq!(mesh.topology(), MeshTopology::Triangles);
+}
+
+#[test]
+fn only_triangle_meshes_can_be_processed() {
+    let node = Pon::from_string("plane_mesh { topology: \"lines\", process: [\"smooth_normals\"] }").unwrap();
+    assert!(pon_to_mesh(Path::new("."), &node, &mut TranslateContext::empty()).is_err());
+}
+
+#[test]
+fn negative_heightmap_chunks_are_rejected() {
+    for source in &["heightmap_mesh { path: \"terrain.png\", chunk: [0, 0], chunk_size: -1 }",
+        "heightmap_mesh { path: \"terrain.png\", chunk: [-1, 0] }"] {
+        let node = Pon::from_string(source).unwrap();
+        match pon_to_mesh(Path::new("."), &node, &mut TranslateContext::empty()) {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            other => panic!("Expected an invalid value for {}, got {:?}", source, other.err())
+        }
+    }
+}
+
+#[test]
+fn negative_draw_ranges_are_rejected() {
+    let node = Pon::from_string("{ start: 6, count: 12 }").unwrap();
+    assert_eq!(node.translate::<DrawRange>(&mut TranslateContext::empty()).ok(), Some(DrawRange { start: 6, count: 12 }));
+    for source in &["{ start: -1, count: 3 }", "{ start: 0, count: -3 }"] {
+        let node = Pon::from_string(source).unwrap();
+        match node.translate::<DrawRange>(&mut TranslateContext::empty()) {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            other => panic!("Expected an invalid value for {}, got {:?}", source, other)
+        }
+    }
+}
@@ -0,0 +1,89 @@
+extern crate cgmath;
+extern crate mesh;
+extern crate pyramid;
+extern crate pyramid_viewport;
+
+use pyramid::pon::*;
+use pyramid_viewport::pon_to_resource::{MeshBuilder, pon_to_mesh};
+use pyramid_viewport::primitives::*;
+
+use cgmath::{Vector3, Zero};
+use mesh::*;
+use std::cmp;
+use std::path::Path;
+
+fn build<B: MeshBuilder + 'static>(builder: B) -> Mesh {
+    Box::new(builder).build().unwrap()
+}
+
+fn attribute(mesh: &Mesh, name: &str, vertex: usize) -> [f32; 3] {
+    let attribute = mesh.layout.attributes.iter().find(|a| a.name == name).unwrap();
+    let start = vertex * mesh.layout.stride + attribute.offset;
+    let mut value = [0.0; 3];
+    for i in 0..cmp::min(attribute.size, 3) {
+        value[i] = mesh.vertex_data[start + i];
+    }
+    value
+}
+
+/// Checks that normals have unit length, and that every triangle is wound counter clockwise when
+/// seen from the side its normals point to.
+fn check_mesh(mesh: &Mesh) {
+    let n_vertices = mesh.vertex_data.len() / mesh.layout.stride;
+    assert!(mesh.element_data.len() > 0 && mesh.element_data.len() % 3 == 0);
+    assert!(mesh.element_data.iter().all(|&i| (i as usize) < n_vertices));
+    for v in 0..n_vertices {
+        let n = attribute(mesh, "normal", v);
+        assert!(((n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt() - 1.0).abs() < 1e-4);
+    }
+    for triangle in mesh.element_data.chunks(3) {
+        let p: Vec<[f32; 3]> = triangle.iter().map(|&i| attribute(mesh, "position", i as usize)).collect();
+        let (a, b) = ([p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]], [p[2][0] - p[0][0], p[2][1] - p[0][1], p[2][2] - p[0][2]]);
+        let face = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+        let normal = triangle.iter().map(|&i| attribute(mesh, "normal", i as usize))
+            .fold([0.0; 3], |sum, n| [sum[0] + n[0], sum[1] + n[1], sum[2] + n[2]]);
+        assert!(face[0] * normal[0] + face[1] * normal[1] + face[2] * normal[2] >= -1e-6);
+    }
+}
+
+#[test]
+fn primitives_have_outward_normals() {
+    let layout = Layout::position_texcoord_normal;
+    let position = Vector3::zero();
+    check_mesh(&build(Plane { layout: layout(), position: position, width: 1.0, height: 1.0, segments_width: 2, segments_height: 3 }));
+    check_mesh(&build(Disc { layout: layout(), position: position, radius: 0.5, segments: 8 }));
+    check_mesh(&build(UvSphere { layout: layout(), position: position, radius: 0.5, segments: 8, rings: 4 }));
+    check_mesh(&build(Icosphere { layout: layout(), position: position, radius: 0.5, subdivisions: 2 }));
+    check_mesh(&build(Cylinder { layout: layout(), position: position, radius: 0.5, height: 1.0, segments: 8, rings: 2, caps: true }));
+    check_mesh(&build(Cone { layout: layout(), position: position, radius: 0.5, height: 1.0, segments: 8, rings: 1, cap: true }));
+    check_mesh(&build(Torus { layout: layout(), position: position, radius: 0.4, tube_radius: 0.1, segments: 8, tube_segments: 6 }));
+    check_mesh(&build(Capsule { layout: layout(), position: position, radius: 0.25, height: 0.5, segments: 8, rings: 3 }));
+}
+
+#[test]
+fn icosphere_vertices_are_shared() {
+    let mesh = build(Icosphere { layout: Layout::position_texcoord_normal(), position: Vector3::zero(), radius: 1.0, subdivisions: 1 });
+    assert_eq!(mesh.vertex_data.len() / mesh.layout.stride, 42);
+    assert_eq!(mesh.element_data.len(), 80 * 3);
+}
+
+#[test]
+fn primitives_fill_only_the_attributes_of_the_layout() {
+    let layout = Layout::new(vec![AttributeSpec("position".to_string(), 3), AttributeSpec("color".to_string(), 4)]);
+    let mesh = build(Plane { layout: layout, position: Vector3::new(1.0, 2.0, 3.0), width: 2.0, height: 2.0, segments_width: 1, segments_height: 1 });
+    assert_eq!(mesh.layout.stride, 7);
+    assert_eq!(&mesh.vertex_data[0..7], &[0.0, 1.0, 3.0, 0.0, 0.0, 0.0, 0.0]);
+}
+
+#[test]
+fn counts_below_the_minimum_are_rejected() {
+    for source in &["sphere_mesh { segments: -1 }", "torus_mesh { tube_segments: 2 }", "plane_mesh { segments_width: 0 }"] {
+        let node = Pon::from_string(source).unwrap();
+        match pon_to_mesh(Path::new("."), &node, &mut TranslateContext::empty()) {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            other => panic!("Expected an invalid value for {}, got {:?}", source, other.err())
+        }
+    }
+    let node = Pon::from_string("sphere_mesh { kind: \"ico\", subdivisions: 0 }").unwrap();
+    assert!(pon_to_mesh(Path::new("."), &node, &mut TranslateContext::empty()).is_ok());
+}
@@ -0,0 +1,21 @@
+extern crate pyramid;
+extern crate pyramid_viewport;
+
+use pyramid::pon::*;
+use pyramid_viewport::pon_to_resource::*;
+
+use std::path::Path;
+
+#[test]
+fn invalid_generator_sizes_are_rejected() {
+    for source in &["checker_texture { width: -1 }", "checker_texture { size: 0 }", "solid_color_texture { height: 0 }",
+        "gradient_texture { width: 0 }", "noise_texture { octaves: 0 }"] {
+        let node = Pon::from_string(source).unwrap();
+        match pon_to_texture(Path::new("."), &node, &mut TranslateContext::empty()) {
+            Err(PonTranslateErr::InvalidValue { .. }) => {},
+            other => panic!("Expected an invalid value for {}, got {:?}", source, other.err())
+        }
+    }
+    let node = Pon::from_string("checker_texture { width: 4, height: 4, size: 1 }").unwrap();
+    assert!(pon_to_texture(Path::new("."), &node, &mut TranslateContext::empty()).is_ok());
+}
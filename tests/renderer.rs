@@ -0,0 +1,497 @@
+//! Renderer tests against the `RecordingBackend`; these need no GL context.
+
+extern crate gl;
+extern crate cgmath;
+extern crate image;
+extern crate mesh;
+extern crate pyramid_viewport;
+
+use pyramid_viewport::backend::*;
+use pyramid_viewport::gl_resources::*;
+use pyramid_viewport::pon_to_resource::*;
+use pyramid_viewport::renderer::*;
+use pyramid_viewport::shader_uniforms::*;
+
+use cgmath::*;
+use gl::types::*;
+use image::RgbaImage;
+use mesh::*;
+use std::rc::Rc;
+
+struct Fixture {
+    recording: Rc<RecordingBackend>,
+    backend: Rc<RenderBackend>,
+    shader: Rc<GLShaderProgram>,
+    vertex_array: Rc<GLVertexArray>
+}
+
+impl Fixture {
+    fn new() -> Fixture {
+        let recording = Rc::new(RecordingBackend::new());
+        let backend: Rc<RenderBackend> = recording.clone();
+        let shader = Rc::new(GLShaderProgram::new(&backend,
+            &GLShader::new(&backend, "", gl::VERTEX_SHADER, "test"),
+            &GLShader::new(&backend, "", gl::FRAGMENT_SHADER, "test")));
+        let mesh = Rc::new(GLMesh::new(&backend, &Mesh {
+            layout: Layout::position_texcoord_normal(),
+            vertex_data: vec![0.0; 8 * 3],
+            element_data: vec![0, 1, 2]
+        }));
+        let vertex_array = Rc::new(GLVertexArray::new(&backend, &shader, &mesh));
+        Fixture {
+            recording: recording,
+            backend: backend,
+            shader: shader,
+            vertex_array: vertex_array
+        }
+    }
+    fn shader(&self, vertex_src: &str, fragment_src: &str) -> Rc<GLShaderProgram> {
+        Rc::new(GLShaderProgram::new(&self.backend,
+            &GLShader::new(&self.backend, vertex_src, gl::VERTEX_SHADER, "test"),
+            &GLShader::new(&self.backend, fragment_src, gl::FRAGMENT_SHADER, "test")))
+    }
+    fn renderer(&self) -> Renderer {
+        Renderer::new(self.backend.clone())
+    }
+    fn texture(&self) -> Rc<GLTexture> {
+        Rc::new(GLTexture::new(&self.backend, &Texture::Image(RgbaImage::new(1, 1)), &TextureSampler::default()))
+    }
+    fn node(&self, id: u64, alpha: bool, textures: Vec<(&str, Rc<GLTexture>)>, uniforms: ShaderUniforms) -> RenderNode {
+        RenderNode {
+            id: id,
+            resources: RenderNodeResources {
+                shader: self.shader.clone(),
+                vertex_array: self.vertex_array.clone(),
+                textures: textures.iter().map(|&(_, ref t)| t.clone()).collect()
+            },
+            config: RenderNodeConfig {
+                texture_ids: textures.iter().map(|&(name, _)| name.to_string()).collect(),
+                transform: Matrix4::identity(),
+                uniforms: uniforms,
+                alpha: alpha,
+                draw_range: None
+            }
+        }
+    }
+}
+
+fn draw_count(commands: &[RenderCommand]) -> usize {
+    commands.iter().filter(|c| match **c { RenderCommand::DrawElements { .. } => true, _ => false }).count()
+}
+
+#[test]
+fn opaque_nodes_are_drawn_before_translucent_nodes() {
+    let fixture = Fixture::new();
+    let mut renderer = fixture.renderer();
+    renderer.add_node(fixture.node(1, true, vec![], ShaderUniforms(vec![])));
+    renderer.add_node(fixture.node(2, false, vec![], ShaderUniforms(vec![])));
+    renderer.add_node(fixture.node(3, true, vec![], ShaderUniforms(vec![])));
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    let commands = fixture.recording.commands();
+    let blend = commands.iter().position(|c| *c == RenderCommand::Enable(gl::BLEND)).unwrap();
+    let draws: Vec<usize> = commands.iter().enumerate()
+        .filter(|&(_, c)| match *c { RenderCommand::DrawElements { .. } => true, _ => false })
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(draws.len(), 3);
+    assert!(draws[0] < blend);
+    assert!(draws[1] > blend && draws[2] > blend);
+    assert!(commands[..blend].contains(&RenderCommand::DepthMask(false)));
+}
+
+#[test]
+fn removed_nodes_are_not_drawn() {
+    let fixture = Fixture::new();
+    let mut renderer = fixture.renderer();
+    renderer.add_node(fixture.node(1, false, vec![], ShaderUniforms(vec![])));
+    renderer.add_node(fixture.node(2, true, vec![], ShaderUniforms(vec![])));
+    renderer.remove_node(&2);
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    assert_eq!(draw_count(&fixture.recording.commands()), 1);
+}
+
+#[test]
+fn uniforms_are_written_to_the_node_program() {
+    let fixture = Fixture::new();
+    let mut renderer = fixture.renderer();
+    let uniforms = ShaderUniforms(vec![
+        ("brightness".to_string(), Box::new(0.5f32) as Box<ShaderUniform>),
+        ("tint".to_string(), Box::new(Vector3::new(1.0f32, 2.0, 3.0)) as Box<ShaderUniform>)
+    ]);
+    renderer.add_node(fixture.node(1, false, vec![], uniforms));
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    let program = fixture.shader.program;
+    let commands = fixture.recording.commands();
+    let uniform = |name: &str| commands.iter().filter_map(|c| match *c {
+        RenderCommand::SetUniform { program: p, name: ref n, ref value } if p == program && n == name => Some(value.clone()),
+        _ => None
+    }).next();
+    assert!(uniform("viewProjection").is_some());
+    assert!(uniform("transform").is_some());
+    assert_eq!(uniform("brightness"), Some(UniformValue::Float(0.5)));
+    assert_eq!(uniform("tint"), Some(UniformValue::Vec3(1.0, 2.0, 3.0)));
+}
+
+#[test]
+fn textures_are_assigned_consecutive_units() {
+    let fixture = Fixture::new();
+    let mut renderer = fixture.renderer();
+    let diffuse = fixture.texture();
+    let normal = fixture.texture();
+    renderer.add_node(fixture.node(1, false, vec![("diffuse", diffuse.clone()), ("normal", normal.clone())], ShaderUniforms(vec![])));
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    let commands = fixture.recording.commands();
+    let unit_of = |texture: GLuint| {
+        let bind = commands.iter().position(|c| *c == RenderCommand::BindTexture { target: gl::TEXTURE_2D, texture: texture }).unwrap();
+        commands[..bind].iter().rev().filter_map(|c| match *c {
+            RenderCommand::ActiveTexture(unit) => Some(unit),
+            _ => None
+        }).next().unwrap()
+    };
+    assert_eq!(unit_of(diffuse.texture), 0);
+    assert_eq!(unit_of(normal.texture), 1);
+    assert!(commands.contains(&RenderCommand::SetUniform { program: fixture.shader.program, name: "diffuse".to_string(), value: UniformValue::Int(0) }));
+    assert!(commands.contains(&RenderCommand::SetUniform { program: fixture.shader.program, name: "normal".to_string(), value: UniformValue::Int(1) }));
+}
+
+#[test]
+fn gl_objects_are_deleted_when_dropped() {
+    let fixture = Fixture::new();
+    let texture = fixture.texture();
+    let name = texture.texture;
+    let vao = fixture.vertex_array.vao;
+    let vbo = fixture.vertex_array.mesh.vbo;
+    let program = fixture.shader.program;
+    fixture.recording.clear_commands();
+    let recording = fixture.recording.clone();
+
+    drop(texture);
+    drop(fixture);
+
+    let commands = recording.commands();
+    assert!(commands.contains(&RenderCommand::DeleteTexture(name)));
+    assert!(commands.contains(&RenderCommand::DeleteVertexArray(vao)));
+    assert!(commands.contains(&RenderCommand::DeleteBuffer(vbo)));
+    assert!(commands.contains(&RenderCommand::DeleteProgram(program)));
+}
+
+#[test]
+fn texture_parameters_are_set_at_creation_only() {
+    let fixture = Fixture::new();
+    fixture.recording.clear_commands();
+    let sampler = TextureSampler {
+        min_filter: TextureFilter::Nearest,
+        mipmaps: true,
+        wrap_s: TextureWrap::ClampToEdge,
+        anisotropy: 4.0,
+        ..TextureSampler::default()
+    };
+    let texture = Rc::new(GLTexture::new(&fixture.backend, &Texture::Image(RgbaImage::new(1, 1)), &sampler));
+
+    let commands = fixture.recording.commands();
+    assert!(commands.contains(&RenderCommand::GenerateMipmap(gl::TEXTURE_2D)));
+    assert!(commands.contains(&RenderCommand::TexParameter { target: gl::TEXTURE_2D, pname: gl::TEXTURE_MIN_FILTER, param: gl::NEAREST_MIPMAP_NEAREST as GLint }));
+    assert!(commands.contains(&RenderCommand::TexParameter { target: gl::TEXTURE_2D, pname: gl::TEXTURE_WRAP_S, param: gl::CLAMP_TO_EDGE as GLint }));
+    assert!(commands.contains(&RenderCommand::TexParameterF { target: gl::TEXTURE_2D, pname: 0x84FE, param: 4.0 }));
+
+    let mut renderer = fixture.renderer();
+    renderer.add_node(fixture.node(1, false, vec![("diffuse", texture)], ShaderUniforms(vec![])));
+    fixture.recording.clear_commands();
+    renderer.render();
+
+    assert!(!fixture.recording.commands().iter().any(|c| match *c { RenderCommand::TexParameter { .. } => true, _ => false }));
+}
+
+#[test]
+fn float_textures_use_sized_formats() {
+    let fixture = Fixture::new();
+    fixture.recording.clear_commands();
+    let texture = Texture::Floats { width: 2, height: 1, channels: 4, half: true, data: vec![0.0; 8] };
+    let gl_texture = GLTexture::new(&fixture.backend, &texture, &TextureSampler::default());
+
+    assert!(fixture.recording.commands().contains(&RenderCommand::TexImage2D {
+        target: gl::TEXTURE_2D, internal_format: gl::RGBA16F as GLint, width: 2, height: 1, format: gl::RGBA, ty: gl::FLOAT }));
+    assert_eq!(gl_texture.size_bytes, 16);
+}
+
+#[test]
+fn skybox_is_drawn_after_opaque_nodes_at_the_far_plane() {
+    let fixture = Fixture::new();
+    let mut renderer = fixture.renderer();
+    let faces = (0..6).map(|_| RgbaImage::new(1, 1)).collect();
+    let texture = Rc::new(GLTexture::new(&fixture.backend, &Texture::Cubemap(faces), &TextureSampler::default()));
+    renderer.set_skybox(Some(Skybox {
+        shader: fixture.shader.clone(),
+        vertex_array: fixture.vertex_array.clone(),
+        texture: texture.clone()
+    }));
+    renderer.add_node(fixture.node(1, false, vec![], ShaderUniforms(vec![])));
+    renderer.add_node(fixture.node(2, true, vec![], ShaderUniforms(vec![])));
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    let commands = fixture.recording.commands();
+    let bind_skybox = commands.iter().position(|c| *c == RenderCommand::BindTexture { target: gl::TEXTURE_CUBE_MAP, texture: texture.texture }).unwrap();
+    let draws: Vec<usize> = commands.iter().enumerate()
+        .filter(|&(_, c)| match *c { RenderCommand::DrawElements { .. } => true, _ => false })
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(draws.len(), 3);
+    assert!(draws[0] < bind_skybox && bind_skybox < draws[1]);
+    assert!(commands[..draws[1]].contains(&RenderCommand::DepthFunc(gl::LEQUAL)));
+    assert!(commands[draws[1]..draws[2]].contains(&RenderCommand::DepthFunc(gl::LESS)));
+}
+
+#[test]
+fn dynamic_meshes_are_updated_in_place() {
+    let fixture = Fixture::new();
+    let quad = |n_vertices: usize, element_data: Vec<u32>| Mesh {
+        layout: Layout::position_texcoord_normal(),
+        vertex_data: vec![0.0; 8 * n_vertices],
+        element_data: element_data
+    };
+    let gl_mesh = GLMesh::new_dynamic(&fixture.backend, &quad(4, vec![0, 1, 2, 0, 2, 3]));
+    fixture.recording.clear_commands();
+
+    assert!(gl_mesh.update(&quad(3, vec![0, 1, 2])));
+    let commands = fixture.recording.commands();
+    assert!(commands.contains(&RenderCommand::BufferSubData { target: gl::ARRAY_BUFFER, offset: 0, size: 8 * 3 * 4 }));
+    assert!(!commands.iter().any(|c| match *c { RenderCommand::CreateBuffer { .. } | RenderCommand::BufferData { .. } => true, _ => false }));
+    assert_eq!(gl_mesh.nindices.get(), 3);
+
+    fixture.recording.clear_commands();
+    assert!(gl_mesh.update(&quad(5, vec![0, 1, 2, 0, 2, 3])));
+    assert!(fixture.recording.commands().contains(&RenderCommand::BufferData { target: gl::ARRAY_BUFFER, size: 8 * 5 * 4, usage: gl::DYNAMIC_DRAW }));
+
+    let other_layout = Mesh { layout: Layout::new(vec![AttributeSpec("position".to_string(), 3)]), vertex_data: vec![0.0; 9], element_data: vec![0, 1, 2] };
+    assert!(!gl_mesh.update(&other_layout));
+    assert!(!GLMesh::new(&fixture.backend, &quad(4, vec![0, 1, 2])).update(&quad(4, vec![0, 1, 2])));
+}
+
+#[test]
+fn attributes_are_packed_with_their_component_types() {
+    let fixture = Fixture::new();
+    fixture.recording.clear_commands();
+    let layout = Layout::new(vec![
+        AttributeSpec("position".to_string(), 3),
+        AttributeSpec("color".to_string(), 4),
+        AttributeSpec("bones".to_string(), 3)]);
+    let format = VertexFormat(vec![
+        ("color".to_string(), ComponentType::UnsignedByteNormalized),
+        ("bones".to_string(), ComponentType::UnsignedByte)]);
+    let mesh = Rc::new(GLMesh::with_format(&fixture.backend, &Mesh {
+        layout: layout.clone(),
+        vertex_data: vec![
+            0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 1.0, 1.0, 2.0, 3.0,
+            1.0, 0.0, 0.0, 1.0, 0.5, 0.0, 1.0, 1.0, 2.0, 3.0,
+            0.0, 1.0, 0.0, 1.0, 0.5, 0.0, 1.0, 1.0, 2.0, 3.0],
+        element_data: vec![0, 1, 2]
+    }, &format, MeshTopology::Triangles, gl::STATIC_DRAW));
+    // Attributes are aligned to 4 bytes, so the 3 bone indices take up 4
+    assert_eq!(mesh.vertex_layout.stride, 12 + 4 + 4);
+    let packed = mesh.vertex_layout.pack(&layout, &[0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 1.0, 1.0, 2.0, 3.0]);
+    assert_eq!(&packed[12..20], &[255, 128, 0, 255, 1, 2, 3, 0]);
+    let commands = fixture.recording.commands();
+    assert!(commands.contains(&RenderCommand::CreateBuffer { buffer: mesh.vbo, target: gl::ARRAY_BUFFER, size: 3 * 20, usage: gl::STATIC_DRAW }));
+
+    let shader = fixture.shader("in vec3 position;\nin vec4 color;\nin uvec4 bones;", "");
+    fixture.recording.clear_commands();
+    GLVertexArray::new(&fixture.backend, &shader, &mesh);
+    let commands = fixture.recording.commands();
+    assert!(commands.iter().any(|c| match *c {
+        RenderCommand::VertexAttribPointer { ty: gl::UNSIGNED_BYTE, normalized: true, stride: 20, offset: 12, .. } => true,
+        _ => false
+    }));
+    assert!(commands.iter().any(|c| match *c {
+        RenderCommand::VertexAttribIPointer { ty: gl::UNSIGNED_BYTE, stride: 20, offset: 16, .. } => true,
+        _ => false
+    }));
+}
+
+#[test]
+fn small_meshes_use_16_bit_indices() {
+    let fixture = Fixture::new();
+    assert_eq!(fixture.vertex_array.mesh.index_type.get(), gl::UNSIGNED_SHORT);
+    let mut renderer = fixture.renderer();
+    renderer.add_node(fixture.node(1, false, vec![], ShaderUniforms(vec![])));
+    fixture.recording.clear_commands();
+    renderer.render();
+    assert!(fixture.recording.commands().contains(&RenderCommand::DrawElements { mode: gl::TRIANGLES, count: 3, ty: gl::UNSIGNED_SHORT, offset: 0 }));
+
+    let large = GLMesh::new(&fixture.backend, &Mesh {
+        layout: Layout::new(vec![AttributeSpec("position".to_string(), 1)]),
+        vertex_data: vec![0.0; 70000],
+        element_data: vec![0, 1, 69999]
+    });
+    assert_eq!(large.index_type.get(), gl::UNSIGNED_INT);
+
+    // Indices past the end of the vertices are not truncated into range
+    let out_of_range = GLMesh::new(&fixture.backend, &Mesh {
+        layout: Layout::new(vec![AttributeSpec("position".to_string(), 1)]),
+        vertex_data: vec![0.0; 4],
+        element_data: vec![0, 1, 70000]
+    });
+    assert_eq!(out_of_range.index_type.get(), gl::UNSIGNED_INT);
+}
+
+#[test]
+fn meshes_are_drawn_with_their_primitive() {
+    let fixture = Fixture::new();
+    let mesh = Rc::new(GLMesh::with_format(&fixture.backend, &Mesh {
+        layout: Layout::position_texcoord_normal(),
+        vertex_data: vec![0.0; 8 * 4],
+        element_data: vec![0, 1, 2, 3, 0]
+    }, &VertexFormat::default(), MeshTopology::LineStrip, gl::STATIC_DRAW));
+    let mut node = fixture.node(1, false, vec![], ShaderUniforms(vec![]));
+    node.resources.vertex_array = Rc::new(GLVertexArray::new(&fixture.backend, &fixture.shader, &mesh));
+    let mut renderer = fixture.renderer();
+    renderer.add_node(node);
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    assert!(fixture.recording.commands().contains(&RenderCommand::DrawElements { mode: gl::LINE_STRIP, count: 5, ty: gl::UNSIGNED_SHORT, offset: 0 }));
+}
+
+#[test]
+fn draw_ranges_are_clamped_to_the_mesh() {
+    let fixture = Fixture::new();
+    let mesh = Rc::new(GLMesh::new(&fixture.backend, &Mesh {
+        layout: Layout::position_texcoord_normal(),
+        vertex_data: vec![0.0; 8 * 4],
+        element_data: vec![0, 1, 2, 0, 2, 3]
+    }));
+    let vertex_array = Rc::new(GLVertexArray::new(&fixture.backend, &fixture.shader, &mesh));
+    let mut renderer = fixture.renderer();
+    for &(id, start, count) in &[(1, 3, 3), (2, 3, 100), (3, 10, 3)] {
+        let mut node = fixture.node(id, false, vec![], ShaderUniforms(vec![]));
+        node.resources.vertex_array = vertex_array.clone();
+        node.config.draw_range = Some(DrawRange { start: start, count: count });
+        renderer.add_node(node);
+    }
+    fixture.recording.clear_commands();
+
+    renderer.render();
+
+    let draws: Vec<RenderCommand> = fixture.recording.commands().into_iter()
+        .filter(|c| match *c { RenderCommand::DrawElements { .. } => true, _ => false })
+        .collect();
+    // Offsets are in bytes of 16-bit indices
+    assert_eq!(draws, vec![
+        RenderCommand::DrawElements { mode: gl::TRIANGLES, count: 3, ty: gl::UNSIGNED_SHORT, offset: 6 },
+        RenderCommand::DrawElements { mode: gl::TRIANGLES, count: 3, ty: gl::UNSIGNED_SHORT, offset: 6 },
+        RenderCommand::DrawElements { mode: gl::TRIANGLES, count: 0, ty: gl::UNSIGNED_SHORT, offset: 12 }]);
+}
+
+#[test]
+fn shader_programs_reflect_their_inputs() {
+    let fixture = Fixture::new();
+    let shader = fixture.shader(
+        "in vec3 position;\nin vec2 texcoord;\nuniform mat4 transform;",
+        "in vec2 Texcoord;\nuniform sampler2D diffuse;");
+    let attributes: Vec<(&str, GLenum)> = shader.attributes.iter().map(|a| (a.name.as_str(), a.ty)).collect();
+    assert_eq!(attributes, vec![("position", gl::FLOAT_VEC3), ("texcoord", gl::FLOAT_VEC2)]);
+    assert_eq!(shader.uniform("transform").map(|u| u.ty), Some(gl::FLOAT_MAT4));
+    assert_eq!(shader.uniform("diffuse").map(|u| u.ty), Some(gl::SAMPLER_2D));
+    assert!(shader.attribute("Texcoord").is_none());
+}
+
+#[test]
+fn mesh_attributes_the_shader_does_not_use_are_skipped() {
+    let fixture = Fixture::new();
+    let shader = fixture.shader("in vec3 position;", "");
+    let mesh = fixture.vertex_array.mesh.clone();
+    fixture.recording.clear_commands();
+
+    GLVertexArray::new(&fixture.backend, &shader, &mesh);
+
+    let commands = fixture.recording.commands();
+    let position = shader.attribute("position").unwrap().location as GLuint;
+    assert_eq!(commands.iter().filter(|c| match **c { RenderCommand::EnableVertexAttribArray(_) => true, _ => false }).count(), 1);
+    assert!(commands.contains(&RenderCommand::EnableVertexAttribArray(position)));
+}
+
+#[test]
+fn shader_inputs_the_mesh_can_not_provide_are_reported() {
+    let fixture = Fixture::new();
+    let mesh = fixture.vertex_array.mesh.clone();
+
+    let shader = fixture.shader("in vec3 position;\nin vec4 tangent;", "");
+    fixture.recording.clear_commands();
+    let vertex_array = GLVertexArray::new(&fixture.backend, &shader, &mesh);
+    assert_eq!(vertex_array.diagnostics.len(), 1);
+    assert!(vertex_array.diagnostics[0].contains("tangent is missing"), "{}", vertex_array.diagnostics[0]);
+    let position = shader.attribute("position").unwrap().location as GLuint;
+    assert!(fixture.recording.commands().contains(&RenderCommand::EnableVertexAttribArray(position)));
+
+    let shader = fixture.shader("in vec3 position;\nin ivec3 normal;", "");
+    fixture.recording.clear_commands();
+    let vertex_array = GLVertexArray::new(&fixture.backend, &shader, &mesh);
+    assert_eq!(vertex_array.diagnostics.len(), 1);
+    assert!(vertex_array.diagnostics[0].contains("normal is an integer input"), "{}", vertex_array.diagnostics[0]);
+    let normal = shader.attribute("normal").unwrap().location as GLuint;
+    assert!(!fixture.recording.commands().contains(&RenderCommand::EnableVertexAttribArray(normal)));
+
+    let shader = fixture.shader("in vec3 position;", "");
+    assert!(GLVertexArray::new(&fixture.backend, &shader, &mesh).diagnostics.is_empty());
+}
+
+#[test]
+fn color_buffer_readback_goes_through_the_backend() {
+    let fixture = Fixture::new();
+    let renderer = fixture.renderer();
+    // GL returns the bottom row first
+    fixture.recording.set_read_pixels(vec![1, 1, 1, 255, 2, 2, 2, 255]);
+    fixture.recording.clear_commands();
+
+    let image = renderer.read_color_buffer(1, 2);
+
+    assert_eq!(fixture.recording.commands(), vec![
+        RenderCommand::ReadPixels { width: 1, height: 2, format: gl::RGBA, ty: gl::UNSIGNED_BYTE }]);
+    assert_eq!(image.get_pixel(0, 0).data, [2, 2, 2, 255]);
+    assert_eq!(image.get_pixel(0, 1).data, [1, 1, 1, 255]);
+}
+
+#[test]
+fn float_textures_with_invalid_channels_are_errors() {
+    let fixture = Fixture::new();
+    let texture = Texture::Floats { width: 1, height: 1, channels: 5, half: false, data: vec![0.0; 5] };
+    match GLTexture::try_new(&fixture.backend, &texture, &TextureSampler::default()) {
+        Err(ResourceErr::Texture(_)) => {},
+        other => panic!("Expected a texture error, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn framebuffers_are_created_and_deleted_through_the_backend() {
+    let fixture = Fixture::new();
+    fixture.recording.clear_commands();
+
+    let framebuffer = GLFramebuffer::new(&fixture.backend, 4, 2).unwrap();
+    let (fbo, color, depth) = (framebuffer.fbo, framebuffer.color, framebuffer.depth);
+    drop(framebuffer);
+
+    assert_eq!(fixture.recording.commands(), vec![
+        RenderCommand::CreateFramebuffer(fbo),
+        RenderCommand::BindFramebuffer { target: gl::FRAMEBUFFER, framebuffer: fbo },
+        RenderCommand::CreateRenderbuffer { renderbuffer: color, internal_format: gl::RGBA8, width: 4, height: 2 },
+        RenderCommand::FramebufferRenderbuffer { attachment: gl::COLOR_ATTACHMENT0, renderbuffer: color },
+        RenderCommand::CreateRenderbuffer { renderbuffer: depth, internal_format: gl::DEPTH_COMPONENT24, width: 4, height: 2 },
+        RenderCommand::FramebufferRenderbuffer { attachment: gl::DEPTH_ATTACHMENT, renderbuffer: depth },
+        RenderCommand::DeleteFramebuffer(fbo),
+        RenderCommand::DeleteRenderbuffer(color),
+        RenderCommand::DeleteRenderbuffer(depth)]);
+}